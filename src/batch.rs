@@ -0,0 +1,256 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use fewerpngbits::OutputFormat;
+use image::ImageFormat;
+
+use crate::Error;
+
+/// A file to process.
+#[derive(Debug)]
+pub(crate) struct Job {
+    pub(crate) input: PathBuf,
+    /// `None` to overwrite the input.
+    pub(crate) output: Option<PathBuf>,
+}
+
+/// Find all files to process in `paths`, descending into directories.
+///
+/// With an `out_dir`, the structure of each input directory is mirrored inside of it.
+/// Files that were named explicitly are always processed, `include` and `exclude` only filter
+/// the content of directories.
+pub(crate) fn collect_jobs(
+    paths: &[PathBuf],
+    out_dir: Option<&Path>,
+    include: &[Glob],
+    exclude: &[Glob],
+    format: OutputFormat,
+    errors: &mut Vec<Error>,
+) -> Vec<Job> {
+    let mut walker = Walker {
+        include,
+        exclude,
+        format,
+        jobs: Vec::new(),
+        errors,
+    };
+    for path in paths {
+        if path.is_dir() {
+            walker.walk(path, out_dir, Path::new(""));
+        } else {
+            let output = out_dir.map(|dir| match path.file_name() {
+                Some(name) => dir.join(name),
+                None => dir.join(path),
+            });
+            walker.jobs.push(Job {
+                input: path.clone(),
+                output: output_path(path, output, format),
+            });
+        }
+    }
+    walker.jobs
+}
+
+struct Walker<'a> {
+    include: &'a [Glob],
+    exclude: &'a [Glob],
+    format: OutputFormat,
+    jobs: Vec<Job>,
+    errors: &'a mut Vec<Error>,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, root: &Path, out_dir: Option<&Path>, relative: &Path) {
+        let dir = root.join(relative);
+        let entries = match read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) => return self.errors.push(Error::ReadDir(err, dir)),
+        };
+        let mut entries = match entries.collect::<Result<Vec<_>, _>>() {
+            Ok(entries) => entries,
+            Err(err) => return self.errors.push(Error::ReadDir(err, dir)),
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let relative = relative.join(entry.file_name());
+            if self.exclude.iter().any(|glob| glob.matches(&relative)) {
+                continue;
+            }
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                self.walk(root, out_dir, &relative);
+            } else if entry.path().is_file() && self.is_included(&relative) {
+                let output = out_dir.map(|dir| dir.join(&relative));
+                self.jobs.push(Job {
+                    output: output_path(&entry.path(), output, self.format),
+                    input: entry.path(),
+                });
+            }
+        }
+    }
+
+    fn is_included(&self, relative: &Path) -> bool {
+        if self.include.is_empty() {
+            is_readable(relative)
+        } else {
+            self.include.iter().any(|glob| glob.matches(relative))
+        }
+    }
+}
+
+/// Where to write the result for `input`, given the derived `output` for files already in
+/// `format`.
+///
+/// Other files are converted, so instead of being overwritten they get a sibling file with the
+/// extension of `format`, and their output in an output directory gets that extension, too.
+/// Explicitly named outputs must not be passed in, they are used as they are.
+pub(crate) fn output_path(
+    input: &Path,
+    output: Option<PathBuf>,
+    format: OutputFormat,
+) -> Option<PathBuf> {
+    if has_extension(input, format.extension()) {
+        return output;
+    }
+    Some(
+        output
+            .unwrap_or_else(|| input.to_owned())
+            .with_extension(format.extension()),
+    )
+}
+
+/// Whether `path` has the extension of an image format that can be decoded.
+fn is_readable(path: &Path) -> bool {
+    path.extension()
+        .and_then(ImageFormat::from_extension)
+        .is_some_and(|format| format.reading_enabled())
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// A shell-like pattern: `?` matches one character, `*` matches any characters except `/`,
+/// and `**` matches any characters including `/`.
+///
+/// Patterns without a `/` are matched against the file name only, other patterns against the
+/// path relative to the input directory. A leading `./` anchors a pattern to the input directory.
+#[derive(Debug, Clone)]
+pub(crate) struct Glob {
+    pattern: String,
+    /// Whether the pattern is matched against the relative path instead of the file name.
+    anchored: bool,
+}
+
+impl Glob {
+    fn matches(&self, relative: &Path) -> bool {
+        let text = if self.anchored {
+            let components: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            components.join("/")
+        } else {
+            match relative.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => return false,
+            }
+        };
+        let pattern: Vec<char> = self.pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        glob_match(&pattern, &text)
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            glob_match(rest, text)
+                || (0..text.len())
+                    .filter(|&i| text[i] == '/')
+                    .any(|i| glob_match(rest, &text[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&skip| skip == 0 || text[skip - 1] != '/')
+            .any(|skip| glob_match(rest, &text[skip..])),
+        ['?', rest @ ..] => match text {
+            [c, text @ ..] => *c != '/' && glob_match(rest, text),
+            [] => false,
+        },
+        [p, rest @ ..] => match text {
+            [c, text @ ..] => p == c && glob_match(rest, text),
+            [] => false,
+        },
+    }
+}
+
+impl FromStr for Glob {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_ascii();
+        match s.trim_start_matches("./") {
+            "" => Err("expected a non-empty pattern"),
+            pattern => Ok(Self {
+                pattern: pattern.to_owned(),
+                anchored: pattern.len() < s.len() || pattern.contains('/'),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, relative: &str) -> bool {
+        pattern
+            .parse::<Glob>()
+            .unwrap()
+            .matches(Path::new(relative))
+    }
+
+    #[test]
+    fn double_star_slash() {
+        assert!(matches("**/icons/*.png", "icons/a.png"));
+        assert!(matches("**/icons/*.png", "assets/icons/a.png"));
+        assert!(matches("**/icons/*.png", "assets/big/icons/a.png"));
+        assert!(!matches("**/icons/*.png", "assets/myicons/a.png"));
+        assert!(matches("assets/**/*.png", "assets/a.png"));
+        assert!(matches("assets/**/*.png", "assets/big/icons/a.png"));
+        assert!(matches("assets/**", "assets/big/icons/a.png"));
+    }
+
+    #[test]
+    fn star_does_not_cross_slash() {
+        assert!(matches("assets/*.png", "assets/a.png"));
+        assert!(!matches("assets/*.png", "assets/icons/a.png"));
+        assert!(!matches("*/a.png", "assets/icons/a.png"));
+        assert!(matches("assets/?.png", "assets/a.png"));
+        assert!(!matches("assets?a.png", "assets/a.png"));
+    }
+
+    #[test]
+    fn file_name_only() {
+        assert!(matches("*.png", "assets/icons/a.png"));
+        assert!(matches("a.png", "assets/a.png"));
+        assert!(!matches("*.png", "assets/a.png.bak"));
+    }
+
+    #[test]
+    fn leading_dot_slash_anchors() {
+        assert!(matches("./a.png", "a.png"));
+        assert!(!matches("./a.png", "assets/a.png"));
+        assert!(matches("./*.png", "a.png"));
+        assert!(!matches("./*.png", "assets/a.png"));
+        assert!(matches("./assets/*.png", "assets/a.png"));
+        assert!("./".parse::<Glob>().is_err());
+    }
+}
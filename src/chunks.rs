@@ -0,0 +1,305 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+use crate::fill::FillMode;
+use crate::{ChannelBits, StripChunks, TargetColors};
+
+pub(crate) const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Ancillary chunks that describe how the input was encoded rather than the image itself,
+/// so they are wrong after the image was decoded and re-encoded.
+const ENCODING_CHUNKS: &[&[u8; 4]] = &[
+    b"tRNS", b"bKGD", b"hIST", b"sBIT", b"acTL", b"fcTL", b"fdAT",
+];
+
+/// Copy the ancillary chunks of `input` into `encoded`, which has none of its own.
+///
+/// Chunks that came after the image data in `input` are placed after it in the result, too.
+pub(crate) fn carry_ancillary_chunks(input: &[u8], encoded: &[u8]) -> Vec<u8> {
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    let mut seen_idat = false;
+    for chunk in chunks(input) {
+        let name = chunk.name();
+        if name == b"IDAT" {
+            seen_idat = true;
+        } else if name[0].is_ascii_lowercase() && !ENCODING_CHUNKS.contains(&name) {
+            match seen_idat {
+                false => before.extend_from_slice(chunk.bytes),
+                true => after.extend_from_slice(chunk.bytes),
+            }
+        }
+    }
+    if before.is_empty() && after.is_empty() {
+        return encoded.to_vec();
+    }
+
+    let mut result = Vec::with_capacity(encoded.len() + before.len() + after.len());
+    result.extend_from_slice(SIGNATURE);
+    for chunk in chunks(encoded) {
+        match chunk.name() {
+            b"IEND" => result.extend_from_slice(&after),
+            b"IDAT" if !before.is_empty() => result.extend_from_slice(&std::mem::take(&mut before)),
+            _ => {}
+        }
+        result.extend_from_slice(chunk.bytes);
+    }
+    result
+}
+
+/// Whether a color space chunk of `input` that is carried over with `strip` describes RGB data,
+/// so the image must not be turned into gray.
+///
+/// Like oxipng, this holds for an iCCP chunk that is kept, and for an sRGB chunk if nothing is
+/// stripped at all.
+pub(crate) fn forbids_gray(input: &[u8], strip: &StripChunks) -> bool {
+    chunks(input).any(|chunk| match (chunk.name(), strip) {
+        (_, StripChunks::All) => false,
+        (b"iCCP", StripChunks::Keep(names)) => names.contains(b"iCCP"),
+        (b"iCCP", StripChunks::Strip(names)) => !names.contains(b"iCCP"),
+        (b"iCCP", StripChunks::Safe | StripChunks::None) => true,
+        (b"sRGB", StripChunks::None) => true,
+        _ => false,
+    })
+}
+
+/// Bit depth and color type of a PNG file, as stated in its IHDR chunk.
+pub(crate) fn header(png: &[u8]) -> Option<(u8, u8)> {
+    let ihdr = chunks(png).next().filter(|chunk| chunk.name() == b"IHDR")?;
+    let &[.., depth, color_type, _, _, _] = ihdr.data().first_chunk::<13>()?;
+    Some((depth, color_type))
+}
+
+/// Reduce the bits of the palette entries of an indexed PNG, leaving its image data untouched.
+///
+/// The tRNS chunk is extended if masking made formerly opaque entries translucent,
+/// and dropped if every entry ended up opaque.
+pub(crate) fn mask_palette(png: &[u8], bits: ChannelBits, fill: FillMode) -> Vec<u8> {
+    let plte = chunks(png).find(|chunk| chunk.name() == b"PLTE");
+    let trns = chunks(png).find(|chunk| chunk.name() == b"tRNS");
+    let Some(plte) = plte else {
+        return png.to_vec();
+    };
+
+    let (colors, _) = plte.data().as_chunks::<3>();
+    let alphas = trns.map_or(&[][..], |chunk| chunk.data());
+    let mut entries = colors
+        .iter()
+        .enumerate()
+        .map(|(index, &[r, g, b])| [r, g, b, alphas.get(index).copied().unwrap_or(u8::MAX)])
+        .collect::<Vec<_>>();
+    // the palette is a single row of entries
+    let width = entries.len() as u32;
+    bits.run(entries.as_flattened_mut(), width, TargetColors::Rgba8, fill);
+
+    let colors = entries
+        .iter()
+        .flat_map(|&[r, g, b, _]| [r, g, b])
+        .collect::<Vec<_>>();
+    let alphas = entries.iter().map(|&[.., a]| a).collect::<Vec<_>>();
+    let opaque = alphas
+        .iter()
+        .rposition(|&a| a != u8::MAX)
+        .map_or(0, |index| index + 1);
+
+    let mut result = Vec::with_capacity(png.len() + alphas.len());
+    result.extend_from_slice(SIGNATURE);
+    for chunk in chunks(png) {
+        match chunk.name() {
+            b"PLTE" => {
+                write_chunk(&mut result, *b"PLTE", &colors);
+                if opaque > 0 {
+                    write_chunk(&mut result, *b"tRNS", &alphas[..opaque]);
+                }
+            }
+            b"tRNS" => {}
+            _ => result.extend_from_slice(chunk.bytes),
+        }
+    }
+    result
+}
+
+/// Number of significant bits of each channel, as recorded in an sBIT chunk.
+///
+/// Grayscale images use the largest value in `color` for their gray channel.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sbit {
+    pub(crate) color: [u8; 3],
+    pub(crate) alpha: u8,
+}
+
+impl Sbit {
+    pub(crate) fn new(bits: ChannelBits, target_colors: TargetColors) -> Self {
+        let depth = target_colors.depth();
+        let color = match target_colors.color_channels() {
+            1 => [bits.gray(); 3],
+            _ => bits.color,
+        };
+        Self {
+            color: color.map(|bits| bits.get().min(depth)),
+            alpha: bits.alpha.map_or(depth, |alpha| alpha.get().min(depth)),
+        }
+    }
+
+    /// Insert an sBIT chunk that matches the header of `png`, replacing any existing one.
+    ///
+    /// Nothing is inserted if every channel keeps all of its bits.
+    pub(crate) fn insert_into(self, png: &[u8]) -> Vec<u8> {
+        let Some((depth, color_type)) = header(png) else {
+            return png.to_vec();
+        };
+
+        // palette entries always have 8 bits per channel
+        let depth = if color_type == 3 { 8 } else { depth };
+        let [r, g, b] = self.color.map(|bits| bits.min(depth));
+        let gray = r.max(g).max(b);
+        let alpha = self.alpha.min(depth);
+        let data: &[u8] = match color_type {
+            0 => &[gray],
+            2 | 3 => &[r, g, b],
+            4 => &[gray, alpha],
+            6 => &[r, g, b, alpha],
+            _ => return png.to_vec(),
+        };
+
+        let mut result = Vec::with_capacity(png.len() + 12 + data.len());
+        result.extend_from_slice(SIGNATURE);
+        for chunk in chunks(png) {
+            match chunk.name() {
+                b"IHDR" => {
+                    result.extend_from_slice(chunk.bytes);
+                    if data.iter().any(|&bits| bits < depth) {
+                        write_chunk(&mut result, *b"sBIT", data);
+                    }
+                }
+                b"sBIT" => {}
+                _ => result.extend_from_slice(chunk.bytes),
+            }
+        }
+        result
+    }
+}
+
+/// A complete chunk of a PNG file: length, name, data and CRC.
+#[derive(Debug, Clone, Copy)]
+struct Chunk<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    fn name(&self) -> &'a [u8; 4] {
+        self.bytes[4..8].try_into().unwrap()
+    }
+
+    fn data(&self) -> &'a [u8] {
+        &self.bytes[8..self.bytes.len() - 4]
+    }
+}
+
+/// Iterate over the chunks of a PNG file, stopping at the first truncated chunk.
+///
+/// Files in other formats have no chunks.
+fn chunks(png: &[u8]) -> impl Iterator<Item = Chunk<'_>> {
+    let mut rest = png.strip_prefix(SIGNATURE).unwrap_or_default();
+    std::iter::from_fn(move || {
+        let (&len, _) = rest.split_first_chunk::<4>()?;
+        let len = usize::try_from(u32::from_be_bytes(len))
+            .ok()?
+            .checked_add(12)?;
+        let (bytes, tail) = rest.split_at_checked(len)?;
+        rest = tail;
+        Some(Chunk { bytes })
+    })
+}
+
+fn write_chunk(output: &mut Vec<u8>, name: [u8; 4], data: &[u8]) {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&name);
+    crc.update(data);
+
+    output.extend_from_slice(&u32::try_from(data.len()).unwrap().to_be_bytes());
+    output.extend_from_slice(&name);
+    output.extend_from_slice(data);
+    output.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1x1 PNG of this color type and depth, with an empty IDAT and the given extra chunks.
+    fn png(depth: u8, color_type: u8, extra: &[([u8; 4], &[u8])]) -> Vec<u8> {
+        let mut png = SIGNATURE.to_vec();
+        write_chunk(
+            &mut png,
+            *b"IHDR",
+            &[0, 0, 0, 1, 0, 0, 0, 1, depth, color_type, 0, 0, 0],
+        );
+        for &(name, data) in extra {
+            write_chunk(&mut png, name, data);
+        }
+        write_chunk(&mut png, *b"IDAT", &[]);
+        write_chunk(&mut png, *b"IEND", &[]);
+        png
+    }
+
+    fn names(png: &[u8]) -> Vec<[u8; 4]> {
+        chunks(png).map(|chunk| *chunk.name()).collect()
+    }
+
+    fn sbit(png: &[u8]) -> Option<Vec<u8>> {
+        chunks(png)
+            .find(|chunk| chunk.name() == b"sBIT")
+            .map(|chunk| chunk.data().to_vec())
+    }
+
+    const SBIT: Sbit = Sbit {
+        color: [5, 6, 5],
+        alpha: 4,
+    };
+
+    #[test]
+    fn every_color_type() {
+        for (depth, color_type, expected) in [
+            (8, 0, &[6][..]),
+            (8, 2, &[5, 6, 5]),
+            (4, 3, &[5, 6, 5]),
+            (8, 4, &[6, 4]),
+            (8, 6, &[5, 6, 5, 4]),
+            (16, 6, &[5, 6, 5, 4]),
+        ] {
+            let result = SBIT.insert_into(&png(depth, color_type, &[]));
+            assert_eq!(
+                sbit(&result).as_deref(),
+                Some(expected),
+                "color type {color_type}"
+            );
+            assert_eq!(names(&result), [*b"IHDR", *b"sBIT", *b"IDAT", *b"IEND"]);
+        }
+    }
+
+    #[test]
+    fn all_bits_kept() {
+        let input = png(8, 2, &[]);
+        let sbit = Sbit {
+            color: [8; 3],
+            alpha: 8,
+        };
+        assert_eq!(sbit.insert_into(&input), input);
+
+        // low-depth gray keeps every bit it has
+        let input = png(2, 0, &[]);
+        assert_eq!(SBIT.insert_into(&input), input);
+    }
+
+    #[test]
+    fn replaces_existing() {
+        let input = png(8, 6, &[(*b"sBIT", &[8, 8, 8, 8]), (*b"tEXt", b"a\0b")]);
+        let result = SBIT.insert_into(&input);
+        assert_eq!(sbit(&result).as_deref(), Some(&[5, 6, 5, 4][..]));
+        assert_eq!(
+            names(&result),
+            [*b"IHDR", *b"sBIT", *b"tEXt", *b"IDAT", *b"IEND"]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+use std::str::FromStr;
+
+use crate::fill::FillMode;
+use crate::{ChannelBits, TargetColors};
+
+/// How to distribute the quantization error before the lower bits are masked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    None,
+    FloydSteinberg,
+    Atkinson,
+    Bayer,
+}
+
+impl Dither {
+    /// Quantize the color channels of `bytes` to the values [`ChannelBits::run`] would produce.
+    ///
+    /// The alpha channel is left untouched.
+    pub(crate) fn run(
+        self,
+        bytes: &mut [u8],
+        width: u32,
+        target_colors: TargetColors,
+        bits: ChannelBits,
+        fill: FillMode,
+    ) {
+        let depth = target_colors.depth();
+        let channels = target_colors.channels();
+        let levels = match target_colors.color_channels() {
+            1 => vec![Levels::new(bits.gray().get(), depth, fill)],
+            _ => bits
+                .color
+                .iter()
+                .map(|bits| Levels::new(bits.get(), depth, fill))
+                .collect(),
+        };
+        if levels.iter().all(Option::is_none) {
+            return;
+        }
+
+        let mut samples = Samples {
+            bytes,
+            depth,
+            channels,
+            width: width.try_into().unwrap(),
+        };
+        match self {
+            Self::None => {}
+            Self::FloydSteinberg => samples.diffuse(&levels, FLOYD_STEINBERG, 16.0),
+            Self::Atkinson => samples.diffuse(&levels, ATKINSON, 8.0),
+            Self::Bayer => samples.ordered(&levels),
+        }
+    }
+}
+
+impl FromStr for Dither {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_ascii() {
+            "none" => Ok(Self::None),
+            "floyd-steinberg" => Ok(Self::FloydSteinberg),
+            "atkinson" => Ok(Self::Atkinson),
+            "bayer" => Ok(Self::Bayer),
+            _ => Err("expected \"none\", \"floyd-steinberg\", \"atkinson\" or \"bayer\""),
+        }
+    }
+}
+
+/// `(dx, dy, weight)` of the neighbors that receive a share of the quantization error.
+type Kernel = &'static [(isize, usize, f32)];
+
+const FLOYD_STEINBERG: Kernel = &[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)];
+
+/// Only 6/8 of the error is distributed, which keeps more contrast.
+const ATKINSON: Kernel = &[
+    (1, 0, 1.0),
+    (2, 0, 1.0),
+    (-1, 1, 1.0),
+    (0, 1, 1.0),
+    (1, 1, 1.0),
+    (0, 2, 1.0),
+];
+
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// The values a channel can take after its lower bits were masked.
+#[derive(Debug, Clone, Copy)]
+struct Levels {
+    mask: u32,
+    max: u32,
+    step: f32,
+    fill: FillMode,
+}
+
+impl Levels {
+    /// `None` if the channel keeps all of its bits.
+    fn new(bits: u8, depth: u8, fill: FillMode) -> Option<Self> {
+        if bits >= depth {
+            return None;
+        }
+        let max = (1 << depth) - 1;
+        let low = max >> bits;
+        Some(Self {
+            mask: max & !low,
+            max,
+            step: (low + 1) as f32,
+            fill,
+        })
+    }
+
+    fn quantize(self, value: f32) -> u32 {
+        let value = value.round().clamp(0.0, self.max as f32) as u32;
+        self.fill.nearest(value, self.mask, self.max)
+    }
+}
+
+struct Samples<'a> {
+    bytes: &'a mut [u8],
+    depth: u8,
+    channels: usize,
+    width: usize,
+}
+
+impl Samples<'_> {
+    fn height(&self) -> usize {
+        let bytes_per_row = self.width * self.channels * usize::from(self.depth / 8);
+        self.bytes.len().checked_div(bytes_per_row).unwrap_or(0)
+    }
+
+    fn get(&self, x: usize, y: usize, channel: usize) -> u32 {
+        let index = (y * self.width + x) * self.channels + channel;
+        match self.depth {
+            8 => self.bytes[index].into(),
+            _ => u16::from_ne_bytes([self.bytes[2 * index], self.bytes[2 * index + 1]]).into(),
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, channel: usize, value: u32) {
+        let index = (y * self.width + x) * self.channels + channel;
+        match self.depth {
+            8 => self.bytes[index] = value as u8,
+            _ => {
+                let [a, b] = (value as u16).to_ne_bytes();
+                self.bytes[2 * index] = a;
+                self.bytes[2 * index + 1] = b;
+            }
+        }
+    }
+
+    /// Error diffusion, keeping the errors of the rows that `kernel` reaches in a ring buffer.
+    fn diffuse(&mut self, levels: &[Option<Levels>], kernel: Kernel, divisor: f32) {
+        let colors = levels.len();
+        let rows = kernel.iter().map(|&(_, dy, _)| dy).max().unwrap_or(0) + 1;
+        let stride = self.width * colors;
+        let mut errors = vec![0.0_f32; rows * stride];
+
+        for y in 0..self.height() {
+            for x in 0..self.width {
+                for (channel, level) in levels.iter().enumerate() {
+                    let Some(level) = level else {
+                        continue;
+                    };
+                    let error = &mut errors[(y % rows) * stride + x * colors + channel];
+                    let value = self.get(x, y, channel) as f32 + std::mem::take(error);
+                    let quantized = level.quantize(value);
+                    self.set(x, y, channel, quantized);
+
+                    let error = (value - quantized as f32) / divisor;
+                    for &(dx, dy, weight) in kernel {
+                        let Some(x) = x.checked_add_signed(dx).filter(|&x| x < self.width) else {
+                            continue;
+                        };
+                        let row = (y + dy) % rows;
+                        errors[row * stride + x * colors + channel] += error * weight;
+                    }
+                }
+            }
+        }
+    }
+
+    fn ordered(&mut self, levels: &[Option<Levels>]) {
+        for y in 0..self.height() {
+            for x in 0..self.width {
+                let threshold = (f32::from(BAYER[y % 8][x % 8]) + 0.5) / 64.0 - 0.5;
+                for (channel, level) in levels.iter().enumerate() {
+                    let Some(level) = level else {
+                        continue;
+                    };
+                    let value = self.get(x, y, channel) as f32 + threshold * level.step;
+                    self.set(x, y, channel, level.quantize(value));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+use std::str::FromStr;
+
+/// What to store in the bits that were discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillMode {
+    /// Half of the discarded range, e.g. `0bABC0_1111`.
+    Midpoint,
+    /// Zeros, e.g. `0bABC0_0000`.
+    Zero,
+    /// Repeat the kept bits, e.g. `0bABCA_BCAB`.
+    Replicate,
+    /// The replicated value that is nearest to the input.
+    Round,
+}
+
+impl FillMode {
+    /// The representable value that is nearest to `value`.
+    ///
+    /// Only the bits in `mask` are kept, `max` is the largest sample value.
+    pub(crate) fn nearest(self, value: u32, mask: u32, max: u32) -> u32 {
+        match self {
+            Self::Midpoint => nearest::<Midpoint>(value, mask, max),
+            Self::Zero => nearest::<Zero>(value, mask, max),
+            Self::Replicate | Self::Round => nearest::<Replicate>(value, mask, max),
+        }
+    }
+}
+
+impl FromStr for FillMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_ascii() {
+            "midpoint" => Ok(Self::Midpoint),
+            "zero" => Ok(Self::Zero),
+            "replicate" => Ok(Self::Replicate),
+            "round" => Ok(Self::Round),
+            _ => Err("expected \"midpoint\", \"zero\", \"replicate\" or \"round\""),
+        }
+    }
+}
+
+/// Compile-time selection of a [`FillMode`], so the masking kernels can be specialized.
+///
+/// All functions are meant to be inlined with constant `mask` and `max` arguments.
+pub(crate) trait Fill {
+    /// The value that is stored for `high`, which has no bits set outside of `mask`.
+    fn reconstruct(high: u32, mask: u32, max: u32) -> u32;
+
+    /// The value that is stored for `value` if only the bits in `mask` are kept.
+    #[inline(always)]
+    fn fill(value: u32, mask: u32, max: u32) -> u32 {
+        Self::reconstruct(value & mask, mask, max)
+    }
+}
+
+pub(crate) struct Midpoint;
+
+pub(crate) struct Zero;
+
+pub(crate) struct Replicate;
+
+pub(crate) struct Round;
+
+impl Fill for Midpoint {
+    #[inline(always)]
+    fn reconstruct(high: u32, mask: u32, max: u32) -> u32 {
+        high | ((max & !mask) >> 1)
+    }
+}
+
+impl Fill for Zero {
+    #[inline(always)]
+    fn reconstruct(high: u32, _: u32, _: u32) -> u32 {
+        high
+    }
+}
+
+impl Fill for Replicate {
+    #[inline(always)]
+    fn reconstruct(high: u32, mask: u32, max: u32) -> u32 {
+        let bits = mask.count_ones();
+        let mut result = high;
+        let mut shift = bits;
+        while bits > 0 && shift < max.count_ones() {
+            result |= high >> shift;
+            shift += bits;
+        }
+        result
+    }
+}
+
+impl Fill for Round {
+    #[inline(always)]
+    fn reconstruct(high: u32, mask: u32, max: u32) -> u32 {
+        Replicate::reconstruct(high, mask, max)
+    }
+
+    #[inline(always)]
+    fn fill(value: u32, mask: u32, max: u32) -> u32 {
+        nearest::<Replicate>(value, mask, max)
+    }
+}
+
+/// Values that share the kept bits lie in one bucket, so only the neighboring buckets can
+/// contain a nearer value.
+#[inline(always)]
+fn nearest<F: Fill>(value: u32, mask: u32, max: u32) -> u32 {
+    let high = value & mask;
+    let step = (max & !mask) + 1;
+    let best = F::reconstruct(high, mask, max);
+    if value > best && high != mask {
+        let next = F::reconstruct(high + step, mask, max);
+        if next - value < value - best {
+            return next;
+        }
+    } else if value < best && high != 0 {
+        let prev = F::reconstruct(high - step, mask, max);
+        if value - prev < best - value {
+            return prev;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask(bits: u32, depth: u32) -> u32 {
+        let max = (1 << depth) - 1;
+        max & !(max >> bits)
+    }
+
+    #[test]
+    fn replicate_8bit() {
+        let max = u8::MAX.into();
+        assert_eq!(Replicate::reconstruct(0x00, mask(1, 8), max), 0x00);
+        assert_eq!(Replicate::reconstruct(0x80, mask(1, 8), max), 0xff);
+        assert_eq!(Replicate::reconstruct(0xa0, mask(3, 8), max), 0b1011_0110);
+        assert_eq!(Replicate::reconstruct(0xe0, mask(3, 8), max), 0xff);
+        assert_eq!(Replicate::reconstruct(0x5a, mask(8, 8), max), 0x5a);
+    }
+
+    #[test]
+    fn replicate_16bit() {
+        let max = u16::MAX.into();
+        assert_eq!(Replicate::reconstruct(0x8000, mask(1, 16), max), 0xffff);
+        assert_eq!(Replicate::reconstruct(0xa000, mask(3, 16), max), 0xb6db);
+        assert_eq!(Replicate::reconstruct(0xb000, mask(5, 16), max), 0xb5ad);
+        assert_eq!(Replicate::reconstruct(0x5a00, mask(8, 16), max), 0x5a5a);
+        assert_eq!(Replicate::reconstruct(0x1234, mask(16, 16), max), 0x1234);
+    }
+
+    #[test]
+    fn round_8bit() {
+        let max = u8::MAX.into();
+        assert_eq!(Round::fill(127, mask(1, 8), max), 0x00);
+        assert_eq!(Round::fill(128, mask(1, 8), max), 0xff);
+        assert_eq!(Round::fill(127, mask(3, 8), max), 109);
+        assert_eq!(Round::fill(128, mask(3, 8), max), 146);
+        assert_eq!(Round::fill(0x5a, mask(8, 8), max), 0x5a);
+    }
+
+    #[test]
+    fn round_16bit() {
+        let max = u16::MAX.into();
+        assert_eq!(Round::fill(0x7fff, mask(1, 16), max), 0x0000);
+        assert_eq!(Round::fill(0x8000, mask(1, 16), max), 0xffff);
+        assert_eq!(Round::fill(0x1234, mask(16, 16), max), 0x1234);
+    }
+
+    /// Round keeps the bits of the nearest value that Replicate can produce.
+    #[test]
+    fn round_is_nearest() {
+        for (depth, bits) in [(8, 1), (8, 3), (8, 8), (16, 1), (16, 3), (16, 8)] {
+            let max = (1 << depth) - 1;
+            let mask = mask(bits, depth);
+            let step = (max & !mask) + 1;
+            let levels = (0..1 << bits)
+                .map(|level| Replicate::reconstruct(level * step, mask, max))
+                .collect::<Vec<_>>();
+            for value in 0..=max {
+                let rounded = Round::fill(value, mask, max);
+                assert!(levels.contains(&rounded), "{value} of {bits} bits");
+                let nearest = levels.iter().map(|&level| level.abs_diff(value)).min();
+                assert_eq!(
+                    Some(rounded.abs_diff(value)),
+                    nearest,
+                    "{value} of {bits} bits"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,1222 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+//! Optimize PNG images by masking the lower bits of each channel.
+//!
+//! The result can be written as a QOI or lossless WebP image instead, too.
+
+mod chunks;
+mod dither;
+mod fill;
+mod palette;
+mod quality;
+mod reduction;
+mod regions;
+
+use std::io::Cursor;
+use std::num::NonZeroU8;
+use std::str::FromStr;
+use std::time::Duration;
+
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{
+    ColorType, DynamicImage, ExtendedColorType, ImageEncoder, ImageError, ImageFormat, ImageReader,
+    RgbImage, RgbaImage,
+};
+use oxipng::{Interlacing, Options, PngError, optimize_from_memory};
+
+pub use oxipng::{Deflaters, IndexSet, RowFilter, StripChunks};
+
+use crate::chunks::{Sbit, carry_ancillary_chunks, forbids_gray, header, mask_palette};
+pub use crate::dither::Dither;
+pub use crate::fill::FillMode;
+use crate::fill::{Fill, Midpoint, Replicate, Round, Zero};
+use crate::palette::{build_palette, encode_indexed, palette_indices, palette_pixels, rgba_pixels};
+pub use crate::quality::{Metrics, Target, compare, diff, side_by_side};
+pub use crate::reduction::Reductions;
+use crate::reduction::{color_pixels, exact_palette, reduce_channels};
+pub use crate::regions::{Region, Regions};
+
+/// How to reduce and optimize an image.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Significant bits to keep, unless `target` chooses the color bits.
+    pub bits: ChannelBits,
+    /// Parts of the image that keep a different number of color bits.
+    pub regions: Regions,
+    /// Keep the fewest color bits between 1 and 8 that meet this target.
+    pub target: Option<Target>,
+    /// Value of the discarded bits.
+    ///
+    /// Grayscale images with 1, 2 or 4 bits per sample always replicate the kept bits, so that
+    /// they can be stored at their low bit depth again.
+    pub fill: FillMode,
+    /// How to distribute the quantization error of the color channels.
+    pub dither: Dither,
+    /// Reduce to an indexed image with at most this many colors (2 to 256).
+    pub palette: Option<u16>,
+    /// Write 16-bit images with 8 bits per channel if at most 8 bits are kept.
+    pub reduce_depth: bool,
+    /// Which metadata chunks to remove.
+    pub strip: StripChunks,
+    /// Record the number of significant bits in an sBIT chunk.
+    pub sbit: bool,
+    /// Optimization level of oxipng, from 0 to 6.
+    pub preset: u8,
+    /// Compression algorithm of the optimized image.
+    pub deflate: Deflaters,
+    /// Filter strategies to try, or `None` for those of the `preset`.
+    pub filters: Option<IndexSet<RowFilter>>,
+    /// Interlace the optimized image with Adam7.
+    pub interlace: bool,
+    /// Choose the filter strategy by a quick compression instead of the final one.
+    pub fast_evaluation: bool,
+    /// Maximum amount of time to spend on optimizations.
+    pub timeout: Option<Duration>,
+    /// Image format to write.
+    pub format: OutputFormat,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bits: ChannelBits {
+                color: [SignificantBits::Bits6; 3],
+                alpha: None,
+                clean_alpha: None,
+                alpha_weighted: false,
+            },
+            regions: Regions::default(),
+            target: None,
+            fill: FillMode::Midpoint,
+            dither: Dither::None,
+            palette: None,
+            reduce_depth: false,
+            strip: StripChunks::All,
+            sbit: true,
+            preset: 6,
+            deflate: Deflaters::Zopfli {
+                iterations: NonZeroU8::new(15).unwrap(),
+            },
+            filters: None,
+            interlace: false,
+            fast_evaluation: false,
+            timeout: Some(Duration::from_secs(30)),
+            format: OutputFormat::Png,
+        }
+    }
+}
+
+/// Image format of the reduced output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// PNG optimized with oxipng.
+    Png,
+    /// The "Quite OK Image" format with 8-bit RGB or RGBA samples, which needs the `qoi` cargo
+    /// feature.
+    Qoi,
+    /// Lossless WebP with 8-bit RGB or RGBA samples, which needs the `webp` cargo feature.
+    WebpLossless,
+}
+
+impl OutputFormat {
+    /// File extension of the format, without a leading dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Qoi => "qoi",
+            Self::WebpLossless => "webp",
+        }
+    }
+
+    /// Whether `data` already is an image in this format.
+    pub fn matches(self, data: &[u8]) -> bool {
+        match self {
+            Self::Png => data.starts_with(chunks::SIGNATURE),
+            Self::Qoi => data.starts_with(b"qoif"),
+            Self::WebpLossless => data.starts_with(b"RIFF") && data.get(8..16) == Some(b"WEBPVP8L"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_ascii() {
+            "png" => Ok(Self::Png),
+            "qoi" => Ok(Self::Qoi),
+            "webp-lossless" => Ok(Self::WebpLossless),
+            _ => Err("expected \"png\", \"qoi\" or \"webp-lossless\""),
+        }
+    }
+}
+
+/// The result of [`reduce`].
+#[derive(Debug, Clone)]
+pub struct Reduced {
+    /// The optimized file in the format of [`Config::format`].
+    pub output: Vec<u8>,
+    /// The bits that were kept, possibly chosen by [`Config::target`].
+    pub bits: ChannelBits,
+    /// Layout of the samples the bits were masked in.
+    pub target_colors: TargetColors,
+    /// Color type reductions that were applied after masking.
+    pub reductions: Reductions,
+}
+
+/// Reduce the bits of an image and optimize it into a PNG, QOI or WebP file.
+///
+/// Input formats other than PNG need their cargo feature.
+pub fn reduce_bits(input: &[u8], config: &Config) -> Result<Vec<u8>, Error> {
+    reduce(input, config).map(|reduced| reduced.output)
+}
+
+/// Reduce the bits of an image and optimize it into a PNG, QOI or WebP file, and tell which bits
+/// were kept.
+///
+/// The result is not compared against the size of `input`.
+pub fn reduce(input: &[u8], config: &Config) -> Result<Reduced, Error> {
+    let bits = config.bits;
+    // regions are masked per pixel, so indexed and low bit depth images are decoded, too
+    let png_header =
+        header(input).filter(|_| config.format == OutputFormat::Png && config.regions.is_empty());
+    let mut reduced = match png_header {
+        // only the palette entries of indexed images need to be masked
+        Some((_, 3)) if config.palette.is_none() => {
+            let bits = match config.target {
+                Some(target) => {
+                    let (image, width, _, target_colors) = decode(input)?;
+                    target.choose(
+                        &image,
+                        width,
+                        target_colors,
+                        bits,
+                        config.fill,
+                        Dither::None,
+                    )
+                }
+                None => bits,
+            };
+            Reduced {
+                output: mask_palette(input, bits, config.fill),
+                bits,
+                target_colors: TargetColors::Rgba8,
+                reductions: Reductions::default(),
+            }
+        }
+        Some((depth @ (1 | 2 | 4), 0)) if config.palette.is_none() => {
+            // the result can only be stored at a low bit depth again if the discarded bits are
+            // replicated
+            let config = Config {
+                fill: FillMode::Replicate,
+                ..config.clone()
+            };
+            let bits = match config.target {
+                Some(target) => {
+                    let (image, width, _, target_colors) = decode(input)?;
+                    target.choose(
+                        &image,
+                        width,
+                        target_colors,
+                        bits,
+                        config.fill,
+                        config.dither,
+                    )
+                }
+                None => bits,
+            };
+            match bits.gray().get() >= depth {
+                // images that already fit are passed through unchanged
+                true => Reduced {
+                    output: input.to_vec(),
+                    bits: ChannelBits {
+                        color: [SignificantBits::ALL[usize::from(depth) - 1]; 3],
+                        ..bits
+                    },
+                    target_colors: TargetColors::L8,
+                    reductions: Reductions::default(),
+                },
+                false => reencode(
+                    input,
+                    &Config {
+                        bits,
+                        target: None,
+                        ..config
+                    },
+                )?,
+            }
+        }
+        _ => reencode(input, config)?,
+    };
+    if config.format != OutputFormat::Png {
+        return Ok(reduced);
+    }
+
+    let mut options = Options {
+        strip: config.strip.clone(),
+        deflate: config.deflate,
+        interlace: Some(match config.interlace {
+            true => Interlacing::Adam7,
+            false => Interlacing::None,
+        }),
+        fast_evaluation: config.fast_evaluation,
+        timeout: config.timeout,
+        ..Options::from_preset(config.preset)
+    };
+    if let Some(filters) = &config.filters {
+        options.filter = filters.clone();
+    }
+    reduced.output = optimize_from_memory(&reduced.output, &options).map_err(Error::Optimize)?;
+    if config.sbit {
+        let sbit = Sbit::new(reduced.bits, reduced.target_colors);
+        reduced.output = sbit.insert_into(&reduced.output);
+    }
+    Ok(reduced)
+}
+
+/// Dither and mask a decoded image in place, and return the bits that were kept.
+pub fn mask_image(image: &mut DynamicImage, config: &Config) -> Result<ChannelBits, Error> {
+    let width = image.width();
+    let target_colors = TargetColors::try_from(image.color()).map_err(Error::ColorType)?;
+    match image {
+        DynamicImage::ImageLuma8(image) => mask_slice(image, width, target_colors, config),
+        DynamicImage::ImageLumaA8(image) => mask_slice(image, width, target_colors, config),
+        DynamicImage::ImageRgb8(image) => mask_slice(image, width, target_colors, config),
+        DynamicImage::ImageRgba8(image) => mask_slice(image, width, target_colors, config),
+        DynamicImage::ImageLuma16(image) => mask_slice16(image, width, target_colors, config),
+        DynamicImage::ImageLumaA16(image) => mask_slice16(image, width, target_colors, config),
+        DynamicImage::ImageRgb16(image) => mask_slice16(image, width, target_colors, config),
+        DynamicImage::ImageRgba16(image) => mask_slice16(image, width, target_colors, config),
+        _ => Err(Error::ColorType(image.color())),
+    }
+}
+
+/// Dither and mask native-endian samples of an image with `width` pixels per row in place, and
+/// return the bits that were kept.
+pub fn mask_slice(
+    bytes: &mut [u8],
+    width: u32,
+    target_colors: TargetColors,
+    config: &Config,
+) -> Result<ChannelBits, Error> {
+    let pixel_size = target_colors.channels() * usize::from(target_colors.depth() / 8);
+    let height = (bytes.len() / pixel_size).checked_div(usize::try_from(width).unwrap());
+    let height = height.map_or(0, |height| u32::try_from(height).unwrap_or(u32::MAX));
+    if config.regions.mismatches(width, height) {
+        return Err(Error::RegionMask);
+    }
+
+    let bits = match config.target {
+        Some(target) => target.choose(
+            bytes,
+            width,
+            target_colors,
+            config.bits,
+            config.fill,
+            config.dither,
+        ),
+        None => config.bits,
+    };
+    if !config.regions.is_empty() {
+        return Ok(config.regions.run(
+            bytes,
+            width,
+            target_colors,
+            bits,
+            config.fill,
+            config.dither,
+        ));
+    }
+    config
+        .dither
+        .run(bytes, width, target_colors, bits, config.fill);
+    bits.run(bytes, width, target_colors, config.fill);
+    Ok(bits)
+}
+
+fn mask_slice16(
+    samples: &mut [u16],
+    width: u32,
+    target_colors: TargetColors,
+    config: &Config,
+) -> Result<ChannelBits, Error> {
+    let mut bytes = samples
+        .iter()
+        .flat_map(|sample| sample.to_ne_bytes())
+        .collect::<Vec<_>>();
+    let bits = mask_slice(&mut bytes, width, target_colors, config)?;
+    let (masked, _) = bytes.as_chunks::<2>();
+    for (sample, &masked) in samples.iter_mut().zip(masked) {
+        *sample = u16::from_ne_bytes(masked);
+    }
+    Ok(bits)
+}
+
+/// Decode the image, reduce its bits and encode it again, without optimizing it yet.
+fn reencode(input: &[u8], config: &Config) -> Result<Reduced, Error> {
+    let (mut image, width, height, mut target_colors) = decode(input)?;
+
+    // the quality target never chooses more than 8 bits for the color channels
+    let bits = config.bits;
+    let fits_8bit = match config.target {
+        Some(_) => bits.alpha.is_none_or(|alpha| alpha.get() <= 8),
+        None => bits.fits_8bit(),
+    };
+    if config.reduce_depth
+        && fits_8bit
+        && let Some(narrowed) = target_colors.narrowed()
+    {
+        image = narrow_samples(&image);
+        target_colors = narrowed;
+    }
+
+    let bits = mask_slice(&mut image, width, target_colors, config)?;
+
+    let mut encoded = Vec::new();
+    let mut reductions = Reductions::default();
+    if config.format != OutputFormat::Png {
+        // QOI and WebP are written with neither palettes nor 16-bit samples, so the palette is
+        // applied to the pixels
+        let pixels = match config.palette {
+            Some(colors) => {
+                let pixels = palette_pixels(&image, target_colors);
+                let mut palette = build_palette(&pixels, colors.into());
+                mask_entries(&mut palette, bits, target_colors, config.fill);
+                let indices = palette_indices(&pixels, &palette);
+                indices
+                    .iter()
+                    .map(|&index| palette[usize::from(index)])
+                    .collect()
+            }
+            None => rgba_pixels(&image, target_colors),
+        };
+        let alpha = target_colors.channels() > target_colors.color_channels();
+        let format = match config.format {
+            OutputFormat::Qoi => ImageFormat::Qoi,
+            _ => ImageFormat::WebP,
+        };
+        encoded = encode_rgba(&pixels, width, height, alpha, format)?;
+    } else if let Some(colors) = config.palette {
+        let pixels = palette_pixels(&image, target_colors);
+        let mut palette = build_palette(&pixels, colors.into());
+        mask_entries(&mut palette, bits, target_colors, config.fill);
+        encoded = encode_indexed(&pixels, &palette, width, height).map_err(Error::EncodeIndexed)?;
+    } else {
+        // a carried over RGB color profile would be invalid for a gray image
+        let allow_gray = !forbids_gray(input, &config.strip);
+        let (image, encoded_colors) =
+            reduce_channels(image, target_colors, allow_gray, &mut reductions);
+        if let Some(pixels) = color_pixels(&image, encoded_colors)
+            && let Some(palette) = exact_palette(&pixels)
+        {
+            reductions.indexed = true;
+            encoded =
+                encode_indexed(&pixels, &palette, width, height).map_err(Error::EncodeIndexed)?;
+        } else {
+            PngEncoder::new_with_quality(&mut encoded, CompressionType::Fast, FilterType::NoFilter)
+                .write_image(&image, width, height, encoded_colors.into())
+                .map_err(Error::Encode)?;
+        }
+    }
+    if config.strip != StripChunks::All && config.format == OutputFormat::Png {
+        encoded = carry_ancillary_chunks(input, &encoded);
+    }
+    Ok(Reduced {
+        output: encoded,
+        bits,
+        target_colors,
+        reductions,
+    })
+}
+
+/// Encode RGBA pixels as a QOI or lossless WebP image, storing the alpha channel only if `alpha`
+/// is set.
+fn encode_rgba(
+    pixels: &[[u8; 4]],
+    width: u32,
+    height: u32,
+    alpha: bool,
+    format: ImageFormat,
+) -> Result<Vec<u8>, Error> {
+    let image = match alpha {
+        true => DynamicImage::from(
+            RgbaImage::from_raw(width, height, pixels.as_flattened().to_vec()).unwrap(),
+        ),
+        false => {
+            let samples = pixels.iter().flat_map(|&[r, g, b, _]| [r, g, b]).collect();
+            DynamicImage::from(RgbImage::from_raw(width, height, samples).unwrap())
+        }
+    };
+    let mut encoded = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut encoded), format)
+        .map_err(Error::Encode)?;
+    Ok(encoded)
+}
+
+/// Mask RGBA palette entries, which form a single row.
+fn mask_entries(
+    palette: &mut [[u8; 4]],
+    bits: ChannelBits,
+    target_colors: TargetColors,
+    fill: FillMode,
+) {
+    let width = palette.len() as u32;
+    palette_bits(bits, target_colors).run(
+        palette.as_flattened_mut(),
+        width,
+        TargetColors::Rgba8,
+        fill,
+    );
+}
+
+/// The bits to keep in RGBA palette entries; gray images use their gray bits for every channel.
+fn palette_bits(bits: ChannelBits, target_colors: TargetColors) -> ChannelBits {
+    match target_colors.color_channels() {
+        1 => ChannelBits {
+            color: [bits.gray(); 3],
+            ..bits
+        },
+        _ => bits,
+    }
+}
+
+/// Decode the image into native-endian samples.
+fn decode(input: &[u8]) -> Result<(Vec<u8>, u32, u32, TargetColors), Error> {
+    let (image, target_colors) = load(input)?;
+    let (width, height) = (image.width(), image.height());
+    Ok((image.into_bytes(), width, height, target_colors))
+}
+
+/// Decode an image in any enabled format, which is guessed from its content.
+///
+/// Sample types the masking kernels do not support, e.g. floating point, are converted to 16 bits.
+pub(crate) fn load(input: &[u8]) -> Result<(DynamicImage, TargetColors), Error> {
+    let mut reader = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(|err| Error::Header(err.into()))?;
+    reader.no_limits();
+    let decoder = reader.into_decoder().map_err(Error::Header)?;
+    let image = DynamicImage::from_decoder(decoder).map_err(Error::Read)?;
+
+    let target_colors = match TargetColors::try_from(image.color()) {
+        Ok(target_colors) => return Ok((image, target_colors)),
+        Err(color_type) if color_type.has_alpha() => TargetColors::Rgba16,
+        Err(_) => TargetColors::Rgb16,
+    };
+    Ok((convert(image, target_colors), target_colors))
+}
+
+/// Convert a decoded image into the layout of `target_colors`.
+pub(crate) fn convert(image: DynamicImage, target_colors: TargetColors) -> DynamicImage {
+    match target_colors {
+        TargetColors::L8 => image.into_luma8().into(),
+        TargetColors::La8 => image.into_luma_alpha8().into(),
+        TargetColors::Rgb8 => image.into_rgb8().into(),
+        TargetColors::Rgba8 => image.into_rgba8().into(),
+        TargetColors::L16 => image.into_luma16().into(),
+        TargetColors::La16 => image.into_luma_alpha16().into(),
+        TargetColors::Rgb16 => image.into_rgb16().into(),
+        TargetColors::Rgba16 => image.into_rgba16().into(),
+    }
+}
+
+/// Number of significant bits to keep in a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SignificantBits {
+    Bits1 = 1,
+    Bits2 = 2,
+    Bits3 = 3,
+    Bits4 = 4,
+    Bits5 = 5,
+    Bits6 = 6,
+    Bits7 = 7,
+    Bits8 = 8,
+    Bits9 = 9,
+    Bits10 = 10,
+    Bits11 = 11,
+    Bits12 = 12,
+    Bits13 = 13,
+    Bits14 = 14,
+    Bits15 = 15,
+    Bits16 = 16,
+}
+
+impl SignificantBits {
+    /// Every value in ascending order, so `ALL[n - 1]` keeps `n` bits.
+    pub const ALL: [Self; 16] = [
+        Self::Bits1,
+        Self::Bits2,
+        Self::Bits3,
+        Self::Bits4,
+        Self::Bits5,
+        Self::Bits6,
+        Self::Bits7,
+        Self::Bits8,
+        Self::Bits9,
+        Self::Bits10,
+        Self::Bits11,
+        Self::Bits12,
+        Self::Bits13,
+        Self::Bits14,
+        Self::Bits15,
+        Self::Bits16,
+    ];
+
+    pub fn get(self) -> u8 {
+        self as u8
+    }
+
+    /// Mask color channels with a compile-time mask, leaving the alpha channel untouched.
+    fn run<F: Fill>(self, bytes: &mut [u8], target_colors: TargetColors) {
+        use SignificantBits::*;
+        use TargetColors::*;
+
+        let func = match (target_colors, self) {
+            (
+                L8 | La8 | Rgb8 | Rgba8,
+                Bits8 | Bits9 | Bits10 | Bits11 | Bits12 | Bits13 | Bits14 | Bits15 | Bits16,
+            ) => return,
+            (L16 | La16 | Rgb16 | Rgba16, Bits16) => return,
+            (L8 | Rgb8, Bits1) => no_alpha::<F, 0b1000_0000>,
+            (L8 | Rgb8, Bits2) => no_alpha::<F, 0b1100_0000>,
+            (L8 | Rgb8, Bits3) => no_alpha::<F, 0b1110_0000>,
+            (L8 | Rgb8, Bits4) => no_alpha::<F, 0b1111_0000>,
+            (L8 | Rgb8, Bits5) => no_alpha::<F, 0b1111_1000>,
+            (L8 | Rgb8, Bits6) => no_alpha::<F, 0b1111_1100>,
+            (L8 | Rgb8, Bits7) => no_alpha::<F, 0b1111_1110>,
+            (La8, Bits1) => la8::<F, 0b1000_0000>,
+            (La8, Bits2) => la8::<F, 0b1100_0000>,
+            (La8, Bits3) => la8::<F, 0b1110_0000>,
+            (La8, Bits4) => la8::<F, 0b1111_0000>,
+            (La8, Bits5) => la8::<F, 0b1111_1000>,
+            (La8, Bits6) => la8::<F, 0b1111_1100>,
+            (La8, Bits7) => la8::<F, 0b1111_1110>,
+            (Rgba8, Bits1) => rgba::<F, 0b1000_0000>,
+            (Rgba8, Bits2) => rgba::<F, 0b1100_0000>,
+            (Rgba8, Bits3) => rgba::<F, 0b1110_0000>,
+            (Rgba8, Bits4) => rgba::<F, 0b1111_0000>,
+            (Rgba8, Bits5) => rgba::<F, 0b1111_1000>,
+            (Rgba8, Bits6) => rgba::<F, 0b1111_1100>,
+            (Rgba8, Bits7) => rgba::<F, 0b1111_1110>,
+            (L16 | Rgb16, Bits1) => no_alpha16::<F, 0b1000_0000_0000_0000>,
+            (L16 | Rgb16, Bits2) => no_alpha16::<F, 0b1100_0000_0000_0000>,
+            (L16 | Rgb16, Bits3) => no_alpha16::<F, 0b1110_0000_0000_0000>,
+            (L16 | Rgb16, Bits4) => no_alpha16::<F, 0b1111_0000_0000_0000>,
+            (L16 | Rgb16, Bits5) => no_alpha16::<F, 0b1111_1000_0000_0000>,
+            (L16 | Rgb16, Bits6) => no_alpha16::<F, 0b1111_1100_0000_0000>,
+            (L16 | Rgb16, Bits7) => no_alpha16::<F, 0b1111_1110_0000_0000>,
+            (L16 | Rgb16, Bits8) => no_alpha16::<F, 0b1111_1111_0000_0000>,
+            (L16 | Rgb16, Bits9) => no_alpha16::<F, 0b1111_1111_1000_0000>,
+            (L16 | Rgb16, Bits10) => no_alpha16::<F, 0b1111_1111_1100_0000>,
+            (L16 | Rgb16, Bits11) => no_alpha16::<F, 0b1111_1111_1110_0000>,
+            (L16 | Rgb16, Bits12) => no_alpha16::<F, 0b1111_1111_1111_0000>,
+            (L16 | Rgb16, Bits13) => no_alpha16::<F, 0b1111_1111_1111_1000>,
+            (L16 | Rgb16, Bits14) => no_alpha16::<F, 0b1111_1111_1111_1100>,
+            (L16 | Rgb16, Bits15) => no_alpha16::<F, 0b1111_1111_1111_1110>,
+            (La16, Bits1) => la16::<F, 0b1000_0000_0000_0000>,
+            (La16, Bits2) => la16::<F, 0b1100_0000_0000_0000>,
+            (La16, Bits3) => la16::<F, 0b1110_0000_0000_0000>,
+            (La16, Bits4) => la16::<F, 0b1111_0000_0000_0000>,
+            (La16, Bits5) => la16::<F, 0b1111_1000_0000_0000>,
+            (La16, Bits6) => la16::<F, 0b1111_1100_0000_0000>,
+            (La16, Bits7) => la16::<F, 0b1111_1110_0000_0000>,
+            (La16, Bits8) => la16::<F, 0b1111_1111_0000_0000>,
+            (La16, Bits9) => la16::<F, 0b1111_1111_1000_0000>,
+            (La16, Bits10) => la16::<F, 0b1111_1111_1100_0000>,
+            (La16, Bits11) => la16::<F, 0b1111_1111_1110_0000>,
+            (La16, Bits12) => la16::<F, 0b1111_1111_1111_0000>,
+            (La16, Bits13) => la16::<F, 0b1111_1111_1111_1000>,
+            (La16, Bits14) => la16::<F, 0b1111_1111_1111_1100>,
+            (La16, Bits15) => la16::<F, 0b1111_1111_1111_1110>,
+            (Rgba16, Bits1) => rgba16::<F, 0b1000_0000_0000_0000>,
+            (Rgba16, Bits2) => rgba16::<F, 0b1100_0000_0000_0000>,
+            (Rgba16, Bits3) => rgba16::<F, 0b1110_0000_0000_0000>,
+            (Rgba16, Bits4) => rgba16::<F, 0b1111_0000_0000_0000>,
+            (Rgba16, Bits5) => rgba16::<F, 0b1111_1000_0000_0000>,
+            (Rgba16, Bits6) => rgba16::<F, 0b1111_1100_0000_0000>,
+            (Rgba16, Bits7) => rgba16::<F, 0b1111_1110_0000_0000>,
+            (Rgba16, Bits8) => rgba16::<F, 0b1111_1111_0000_0000>,
+            (Rgba16, Bits9) => rgba16::<F, 0b1111_1111_1000_0000>,
+            (Rgba16, Bits10) => rgba16::<F, 0b1111_1111_1100_0000>,
+            (Rgba16, Bits11) => rgba16::<F, 0b1111_1111_1110_0000>,
+            (Rgba16, Bits12) => rgba16::<F, 0b1111_1111_1111_0000>,
+            (Rgba16, Bits13) => rgba16::<F, 0b1111_1111_1111_1000>,
+            (Rgba16, Bits14) => rgba16::<F, 0b1111_1111_1111_1100>,
+            (Rgba16, Bits15) => rgba16::<F, 0b1111_1111_1111_1110>,
+        };
+        func(bytes);
+
+        fn no_alpha<F: Fill, const MASK: u8>(bytes: &mut [u8]) {
+            for byte in bytes {
+                mask_bits::<F, MASK>(byte);
+            }
+        }
+
+        fn rgba<F: Fill, const MASK: u8>(bytes: &mut [u8]) {
+            let (chunks, _) = bytes.as_chunks_mut::<4>();
+            for chunk in chunks {
+                mask_bits::<F, MASK>(&mut chunk[0]);
+                mask_bits::<F, MASK>(&mut chunk[1]);
+                mask_bits::<F, MASK>(&mut chunk[2]);
+            }
+        }
+
+        fn la8<F: Fill, const MASK: u8>(bytes: &mut [u8]) {
+            let (chunks, _) = bytes.as_chunks_mut::<2>();
+            for chunk in chunks {
+                mask_bits::<F, MASK>(&mut chunk[0]);
+            }
+        }
+
+        fn no_alpha16<F: Fill, const MASK: u16>(bytes: &mut [u8]) {
+            let (samples, _) = bytes.as_chunks_mut::<2>();
+            for sample in samples {
+                mask_bits16::<F, MASK>(sample);
+            }
+        }
+
+        fn rgba16<F: Fill, const MASK: u16>(bytes: &mut [u8]) {
+            let (chunks, _) = bytes.as_chunks_mut::<8>();
+            for chunk in chunks {
+                let (samples, _) = chunk.as_chunks_mut::<2>();
+                mask_bits16::<F, MASK>(&mut samples[0]);
+                mask_bits16::<F, MASK>(&mut samples[1]);
+                mask_bits16::<F, MASK>(&mut samples[2]);
+            }
+        }
+
+        fn la16<F: Fill, const MASK: u16>(bytes: &mut [u8]) {
+            let (chunks, _) = bytes.as_chunks_mut::<4>();
+            for chunk in chunks {
+                let (samples, _) = chunk.as_chunks_mut::<2>();
+                mask_bits16::<F, MASK>(&mut samples[0]);
+            }
+        }
+
+        #[inline(always)]
+        fn mask_bits<F: Fill, const MASK: u8>(byte: &mut u8) {
+            *byte = F::fill((*byte).into(), MASK.into(), u8::MAX.into()) as u8;
+        }
+
+        /// 16-bit samples are stored in native byte order.
+        #[inline(always)]
+        fn mask_bits16<F: Fill, const MASK: u16>(sample: &mut [u8; 2]) {
+            let value = u16::from_ne_bytes(*sample).into();
+            *sample = (F::fill(value, MASK.into(), u16::MAX.into()) as u16).to_ne_bytes();
+        }
+    }
+}
+
+/// Significant bits to keep for each color channel, and for the alpha channel.
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelBits {
+    pub color: [SignificantBits; 3],
+    pub alpha: Option<AlphaBits>,
+    /// Replace the color of fully transparent pixels.
+    pub clean_alpha: Option<CleanAlpha>,
+    /// Drop one more color bit for each halving of the alpha value, at least one bit is kept.
+    pub alpha_weighted: bool,
+}
+
+impl ChannelBits {
+    /// Grayscale images use the most precise color channel.
+    pub fn gray(self) -> SignificantBits {
+        let [r, g, b] = self.color;
+        r.max(g).max(b)
+    }
+
+    /// Whether no channel needs more than 8 bits.
+    pub fn fits_8bit(self) -> bool {
+        self.color.iter().all(|bits| bits.get() <= 8)
+            && self.alpha.is_none_or(|alpha| alpha.get() <= 8)
+    }
+
+    /// Mask native-endian samples of an image with `width` pixels per row in place, without
+    /// dithering.
+    pub fn run(self, bytes: &mut [u8], width: u32, target_colors: TargetColors, fill: FillMode) {
+        match fill {
+            FillMode::Midpoint => self.run_with::<Midpoint>(bytes, width, target_colors),
+            FillMode::Zero => self.run_with::<Zero>(bytes, width, target_colors),
+            FillMode::Replicate => self.run_with::<Replicate>(bytes, width, target_colors),
+            FillMode::Round => self.run_with::<Round>(bytes, width, target_colors),
+        }
+    }
+
+    fn run_with<F: Fill>(self, bytes: &mut [u8], width: u32, target_colors: TargetColors) {
+        use SignificantBits::*;
+        use TargetColors::*;
+
+        let [r, g, b] = self.color;
+        let gray = self.gray();
+        let alpha = match self.alpha {
+            Some(AlphaBits::Bits(alpha)) => alpha,
+            Some(AlphaBits::Binary) | None => Bits16,
+        };
+        let uniform = r == g && g == b;
+        let keep_alpha = alpha.get() >= target_colors.depth();
+
+        match target_colors {
+            La8 if self.alpha_weighted => weighted::<F, 2>(bytes, [gray, alpha]),
+            Rgba8 if self.alpha_weighted => weighted::<F, 4>(bytes, [r, g, b, alpha]),
+            La16 if self.alpha_weighted => weighted16::<F, 2>(bytes, [gray, alpha]),
+            Rgba16 if self.alpha_weighted => weighted16::<F, 4>(bytes, [r, g, b, alpha]),
+            L8 | L16 => gray.run::<F>(bytes, target_colors),
+            La8 | La16 if keep_alpha => gray.run::<F>(bytes, target_colors),
+            Rgb8 | Rgb16 if uniform => r.run::<F>(bytes, target_colors),
+            Rgba8 | Rgba16 if uniform && keep_alpha => r.run::<F>(bytes, target_colors),
+            La8 => per_channel::<F, 2>(bytes, [mask8(gray), u8::MAX]),
+            Rgb8 => per_channel::<F, 3>(bytes, [r, g, b].map(mask8)),
+            Rgba8 => per_channel::<F, 4>(bytes, [mask8(r), mask8(g), mask8(b), u8::MAX]),
+            La16 => per_channel16::<F, 2>(bytes, [mask16(gray), u16::MAX]),
+            Rgb16 => per_channel16::<F, 3>(bytes, [r, g, b].map(mask16)),
+            Rgba16 => per_channel16::<F, 4>(bytes, [mask16(r), mask16(g), mask16(b), u16::MAX]),
+        }
+        if !keep_alpha && !self.alpha_weighted {
+            replicate_alpha(bytes, target_colors, alpha);
+        }
+        if let Some(AlphaBits::Binary) = self.alpha {
+            binary_alpha(bytes, target_colors);
+        }
+        if let Some(clean) = self.clean_alpha {
+            clean_alpha(bytes, width, target_colors, clean);
+        }
+
+        fn per_channel<F: Fill, const N: usize>(bytes: &mut [u8], masks: [u8; N]) {
+            let (pixels, _) = bytes.as_chunks_mut::<N>();
+            for pixel in pixels {
+                for (byte, &mask) in pixel.iter_mut().zip(&masks) {
+                    *byte = F::fill((*byte).into(), mask.into(), u8::MAX.into()) as u8;
+                }
+            }
+        }
+
+        fn per_channel16<F: Fill, const N: usize>(bytes: &mut [u8], masks: [u16; N]) {
+            let (samples, _) = bytes.as_chunks_mut::<2>();
+            let (pixels, _) = samples.as_chunks_mut::<N>();
+            for pixel in pixels {
+                for (sample, &mask) in pixel.iter_mut().zip(&masks) {
+                    let value = u16::from_ne_bytes(*sample).into();
+                    *sample = (F::fill(value, mask.into(), u16::MAX.into()) as u16).to_ne_bytes();
+                }
+            }
+        }
+
+        /// Mask the alpha channel first, so the color bits depend on the stored alpha value.
+        ///
+        /// Like everywhere else, alpha is filled by replication, whatever the fill mode is.
+        fn weighted<F: Fill, const N: usize>(bytes: &mut [u8], bits: [SignificantBits; N]) {
+            // index the masks by the number of leading zeros of alpha
+            let masks = bits.map(|bits| {
+                let mut masks = [0; 9];
+                for (dropped, mask) in (0..).zip(&mut masks) {
+                    let kept = bits.get().min(8).saturating_sub(dropped).max(1);
+                    *mask = mask8(SignificantBits::ALL[usize::from(kept) - 1]);
+                }
+                masks
+            });
+            let (pixels, _) = bytes.as_chunks_mut::<N>();
+            for pixel in pixels {
+                let alpha = &mut pixel[N - 1];
+                *alpha =
+                    Replicate::fill((*alpha).into(), masks[N - 1][0].into(), u8::MAX.into()) as u8;
+                let dropped = alpha.leading_zeros() as usize;
+                for (byte, masks) in pixel[..N - 1].iter_mut().zip(&masks) {
+                    *byte = F::fill((*byte).into(), masks[dropped].into(), u8::MAX.into()) as u8;
+                }
+            }
+        }
+
+        fn weighted16<F: Fill, const N: usize>(bytes: &mut [u8], bits: [SignificantBits; N]) {
+            let masks = bits.map(|bits| {
+                let mut masks = [0; 17];
+                for (dropped, mask) in (0..).zip(&mut masks) {
+                    let kept = bits.get().saturating_sub(dropped).max(1);
+                    *mask = mask16(SignificantBits::ALL[usize::from(kept) - 1]);
+                }
+                masks
+            });
+            let (samples, _) = bytes.as_chunks_mut::<2>();
+            let (pixels, _) = samples.as_chunks_mut::<N>();
+            for pixel in pixels {
+                let alpha = u16::from_ne_bytes(pixel[N - 1]).into();
+                let alpha = Replicate::fill(alpha, masks[N - 1][0].into(), u16::MAX.into()) as u16;
+                pixel[N - 1] = alpha.to_ne_bytes();
+                let dropped = alpha.leading_zeros() as usize;
+                for (sample, masks) in pixel[..N - 1].iter_mut().zip(&masks) {
+                    let value = u16::from_ne_bytes(*sample).into();
+                    let value = F::fill(value, masks[dropped].into(), u16::MAX.into()) as u16;
+                    *sample = value.to_ne_bytes();
+                }
+            }
+        }
+
+        fn mask8(bits: SignificantBits) -> u8 {
+            !u8::MAX.checked_shr(bits.get().into()).unwrap_or(0)
+        }
+
+        fn mask16(bits: SignificantBits) -> u16 {
+            !u16::MAX.checked_shr(bits.get().into()).unwrap_or(0)
+        }
+    }
+}
+
+impl FromStr for ChannelBits {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERR: &str = "expected 1, 3 or 4 comma-separated values between 1 and 16";
+
+        let mut values = [SignificantBits::Bits16; 4];
+        let mut count = 0;
+        for value in s.split(',') {
+            let Some(slot) = values.get_mut(count) else {
+                return Err(ERR);
+            };
+            *slot = value.parse().map_err(|_| ERR)?;
+            count += 1;
+        }
+
+        let [r, g, b, a] = values;
+        match count {
+            1 => Ok(Self {
+                color: [r; 3],
+                alpha: None,
+                clean_alpha: None,
+                alpha_weighted: false,
+            }),
+            3 => Ok(Self {
+                color: [r, g, b],
+                alpha: None,
+                clean_alpha: None,
+                alpha_weighted: false,
+            }),
+            4 => Ok(Self {
+                color: [r, g, b],
+                alpha: Some(AlphaBits::Bits(a)),
+                clean_alpha: None,
+                alpha_weighted: false,
+            }),
+            _ => Err(ERR),
+        }
+    }
+}
+
+/// Keep `bits` of the alpha channel, filling the rest by replication.
+///
+/// Other fill modes would make fully transparent pixels visible, and opaque pixels translucent.
+fn replicate_alpha(bytes: &mut [u8], target_colors: TargetColors, bits: SignificantBits) {
+    use TargetColors::*;
+
+    match target_colors {
+        L8 | Rgb8 | L16 | Rgb16 => {}
+        La8 => alpha8::<2>(bytes, bits),
+        Rgba8 => alpha8::<4>(bytes, bits),
+        La16 => alpha16::<2>(bytes, bits),
+        Rgba16 => alpha16::<4>(bytes, bits),
+    }
+
+    fn alpha8<const N: usize>(bytes: &mut [u8], bits: SignificantBits) {
+        let mask = !u8::MAX.checked_shr(bits.get().into()).unwrap_or(0);
+        let (pixels, _) = bytes.as_chunks_mut::<N>();
+        for pixel in pixels {
+            let alpha = &mut pixel[N - 1];
+            *alpha = Replicate::fill((*alpha).into(), mask.into(), u8::MAX.into()) as u8;
+        }
+    }
+
+    fn alpha16<const N: usize>(bytes: &mut [u8], bits: SignificantBits) {
+        let mask = !u16::MAX.checked_shr(bits.get().into()).unwrap_or(0);
+        let (samples, _) = bytes.as_chunks_mut::<2>();
+        let (pixels, _) = samples.as_chunks_mut::<N>();
+        for pixel in pixels {
+            let alpha = u16::from_ne_bytes(pixel[N - 1]).into();
+            pixel[N - 1] =
+                (Replicate::fill(alpha, mask.into(), u16::MAX.into()) as u16).to_ne_bytes();
+        }
+    }
+}
+
+/// Collapse the alpha channel to fully transparent or fully opaque.
+fn binary_alpha(bytes: &mut [u8], target_colors: TargetColors) {
+    use TargetColors::*;
+
+    match target_colors {
+        L8 | Rgb8 | L16 | Rgb16 => {}
+        La8 => alpha8::<2>(bytes),
+        Rgba8 => alpha8::<4>(bytes),
+        La16 => alpha16::<2>(bytes),
+        Rgba16 => alpha16::<4>(bytes),
+    }
+
+    fn alpha8<const N: usize>(bytes: &mut [u8]) {
+        let (pixels, _) = bytes.as_chunks_mut::<N>();
+        for pixel in pixels {
+            let alpha = &mut pixel[N - 1];
+            *alpha = if *alpha >= 0x80 { u8::MAX } else { 0 };
+        }
+    }
+
+    fn alpha16<const N: usize>(bytes: &mut [u8]) {
+        let (samples, _) = bytes.as_chunks_mut::<2>();
+        let (pixels, _) = samples.as_chunks_mut::<N>();
+        for pixel in pixels {
+            let alpha = &mut pixel[N - 1];
+            *alpha = if u16::from_ne_bytes(*alpha) >= 0x8000 {
+                u16::MAX
+            } else {
+                0
+            }
+            .to_ne_bytes();
+        }
+    }
+}
+
+/// Replace the color of fully transparent pixels of an image with `width` pixels per row.
+pub(crate) fn clean_alpha(
+    bytes: &mut [u8],
+    width: u32,
+    target_colors: TargetColors,
+    clean: CleanAlpha,
+) {
+    use TargetColors::*;
+
+    let width = usize::try_from(width).unwrap().max(1);
+    match target_colors {
+        L8 | Rgb8 | L16 | Rgb16 => {}
+        La8 => clean8::<2>(bytes, width, clean),
+        Rgba8 => clean8::<4>(bytes, width, clean),
+        La16 => clean16::<2>(bytes, width, clean),
+        Rgba16 => clean16::<4>(bytes, width, clean),
+    }
+
+    fn clean8<const N: usize>(bytes: &mut [u8], width: usize, clean: CleanAlpha) {
+        let (pixels, _) = bytes.as_chunks_mut::<N>();
+        for row in pixels.chunks_mut(width) {
+            // the "sub" filter predicts the first pixel of each row from zeros
+            let mut previous = [0; N];
+            for pixel in row {
+                if pixel[N - 1] == 0 {
+                    pixel[..N - 1].copy_from_slice(match clean {
+                        CleanAlpha::Zero => &[0; N][..N - 1],
+                        CleanAlpha::Previous => &previous[..N - 1],
+                    });
+                }
+                previous = *pixel;
+            }
+        }
+    }
+
+    fn clean16<const N: usize>(bytes: &mut [u8], width: usize, clean: CleanAlpha) {
+        let (samples, _) = bytes.as_chunks_mut::<2>();
+        let (pixels, _) = samples.as_chunks_mut::<N>();
+        for row in pixels.chunks_mut(width) {
+            let mut previous = [[0; 2]; N];
+            for pixel in row {
+                if pixel[N - 1] == [0; 2] {
+                    pixel[..N - 1].copy_from_slice(match clean {
+                        CleanAlpha::Zero => &[[0; 2]; N][..N - 1],
+                        CleanAlpha::Previous => &previous[..N - 1],
+                    });
+                }
+                previous = *pixel;
+            }
+        }
+    }
+}
+
+/// How to reduce the alpha channel.
+#[derive(Debug, Clone, Copy)]
+pub enum AlphaBits {
+    /// Keep this many significant bits.
+    Bits(SignificantBits),
+    /// Only keep fully transparent and fully opaque pixels.
+    Binary,
+}
+
+impl AlphaBits {
+    pub fn get(self) -> u8 {
+        match self {
+            Self::Bits(bits) => bits.get(),
+            Self::Binary => 1,
+        }
+    }
+}
+
+impl FromStr for AlphaBits {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_ascii() {
+            "binary" => Ok(Self::Binary),
+            s => match s.parse() {
+                Ok(bits) => Ok(Self::Bits(bits)),
+                Err(_) => Err("expected value between 1 and 16, or \"binary\""),
+            },
+        }
+    }
+}
+
+/// Which color fully transparent pixels get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanAlpha {
+    /// Black.
+    Zero,
+    /// The color of the preceding pixel in the same row, so that the "sub" filter leaves only
+    /// zeros.
+    Previous,
+}
+
+impl FromStr for CleanAlpha {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_ascii() {
+            "zero" => Ok(Self::Zero),
+            "previous" => Ok(Self::Previous),
+            _ => Err("expected \"zero\" or \"previous\""),
+        }
+    }
+}
+
+/// Channel layout and bit depth of decoded samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetColors {
+    L8,
+    La8,
+    Rgb8,
+    Rgba8,
+    L16,
+    La16,
+    Rgb16,
+    Rgba16,
+}
+
+impl TargetColors {
+    /// Number of samples per pixel.
+    pub fn channels(self) -> usize {
+        match self {
+            Self::L8 | Self::L16 => 1,
+            Self::La8 | Self::La16 => 2,
+            Self::Rgb8 | Self::Rgb16 => 3,
+            Self::Rgba8 | Self::Rgba16 => 4,
+        }
+    }
+
+    /// Number of samples per pixel, not counting the alpha channel.
+    pub fn color_channels(self) -> usize {
+        match self {
+            Self::L8 | Self::La8 | Self::L16 | Self::La16 => 1,
+            Self::Rgb8 | Self::Rgba8 | Self::Rgb16 | Self::Rgba16 => 3,
+        }
+    }
+
+    /// Number of bits per sample.
+    pub fn depth(self) -> u8 {
+        match self {
+            Self::L8 | Self::La8 | Self::Rgb8 | Self::Rgba8 => 8,
+            Self::L16 | Self::La16 | Self::Rgb16 | Self::Rgba16 => 16,
+        }
+    }
+
+    /// The same channel layout with 8 bits per sample, if the image has 16 bits per sample.
+    pub fn narrowed(self) -> Option<Self> {
+        match self {
+            Self::L8 | Self::La8 | Self::Rgb8 | Self::Rgba8 => None,
+            Self::L16 => Some(Self::L8),
+            Self::La16 => Some(Self::La8),
+            Self::Rgb16 => Some(Self::Rgb8),
+            Self::Rgba16 => Some(Self::Rgba8),
+        }
+    }
+}
+
+impl From<TargetColors> for ExtendedColorType {
+    fn from(value: TargetColors) -> ExtendedColorType {
+        match value {
+            TargetColors::L8 => ExtendedColorType::L8,
+            TargetColors::La8 => ExtendedColorType::La8,
+            TargetColors::Rgb8 => ExtendedColorType::Rgb8,
+            TargetColors::Rgba8 => ExtendedColorType::Rgba8,
+            TargetColors::L16 => ExtendedColorType::L16,
+            TargetColors::La16 => ExtendedColorType::La16,
+            TargetColors::Rgb16 => ExtendedColorType::Rgb16,
+            TargetColors::Rgba16 => ExtendedColorType::Rgba16,
+        }
+    }
+}
+
+impl TryFrom<ColorType> for TargetColors {
+    type Error = ColorType;
+
+    fn try_from(value: ColorType) -> Result<Self, Self::Error> {
+        match value {
+            ColorType::L8 => Ok(Self::L8),
+            ColorType::La8 => Ok(Self::La8),
+            ColorType::Rgb8 => Ok(Self::Rgb8),
+            ColorType::Rgba8 => Ok(Self::Rgba8),
+            ColorType::L16 => Ok(Self::L16),
+            ColorType::La16 => Ok(Self::La16),
+            ColorType::Rgb16 => Ok(Self::Rgb16),
+            ColorType::Rgba16 => Ok(Self::Rgba16),
+            value => Err(value),
+        }
+    }
+}
+
+/// Keep only the high byte of each native-endian 16-bit sample.
+pub(crate) fn narrow_samples(bytes: &[u8]) -> Vec<u8> {
+    let (samples, _) = bytes.as_chunks::<2>();
+    samples
+        .iter()
+        .map(|&sample| u16::from_ne_bytes(sample).to_be_bytes()[0])
+        .collect()
+}
+
+impl FromStr for SignificantBits {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_ascii() {
+            "1" => Ok(Self::Bits1),
+            "2" => Ok(Self::Bits2),
+            "3" => Ok(Self::Bits3),
+            "4" => Ok(Self::Bits4),
+            "5" => Ok(Self::Bits5),
+            "6" => Ok(Self::Bits6),
+            "7" => Ok(Self::Bits7),
+            "8" => Ok(Self::Bits8),
+            "9" => Ok(Self::Bits9),
+            "10" => Ok(Self::Bits10),
+            "11" => Ok(Self::Bits11),
+            "12" => Ok(Self::Bits12),
+            "13" => Ok(Self::Bits13),
+            "14" => Ok(Self::Bits14),
+            "15" => Ok(Self::Bits15),
+            "16" => Ok(Self::Bits16),
+            _ => Err("expected value between 1 and 16"),
+        }
+    }
+}
+
+/// Why an image could not be reduced.
+#[derive(pretty_error_debug::Debug, thiserror::Error, displaydoc::Display)]
+pub enum Error {
+    /// Could not decode image header.
+    Header(#[source] ImageError),
+    /// Color type {0:?} is not supported. Only L8, La8, Rgb8, Rgba8, L16, La16, Rgb16 and Rgba16 are.
+    ColorType(ColorType),
+    /// Could not read image data.
+    Read(#[source] ImageError),
+    /// Could not encode image.
+    Encode(#[source] ImageError),
+    /// Could not encode indexed image.
+    EncodeIndexed(#[source] png::EncodingError),
+    /// Could not optimize image.
+    Optimize(#[source] PngError),
+    /// Could not compare images of different dimensions.
+    Dimensions,
+    /// The region mask does not have the dimensions of the image.
+    RegionMask,
+}
@@ -1,250 +1,485 @@
 // SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
 // SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
 
-use std::fs::{File, OpenOptions};
-use std::io::{Cursor, Seek, SeekFrom, Write};
+mod batch;
+mod report;
+
+use std::fs::{
+    File, FileTimes, Metadata, OpenOptions, canonicalize, create_dir_all, metadata, remove_file,
+    rename,
+};
+use std::io::{ErrorKind, Read, StdoutLock, Write, stdin, stdout};
 use std::num::NonZeroU8;
-use std::path::PathBuf;
-use std::process::exit;
+use std::path::{Path, PathBuf};
+use std::process::{self, exit};
 use std::str::FromStr;
 
 use clap::Parser;
-use image::codecs::png::{CompressionType, FilterType, PngDecoder, PngEncoder};
-use image::{ColorType, ExtendedColorType, ImageDecoder, ImageEncoder, ImageError};
-use memmap2::MmapOptions;
-use oxipng::{Deflaters, Options, PngError, StripChunks, optimize_from_memory};
+use fewerpngbits::{
+    AlphaBits, ChannelBits, CleanAlpha, Config, Deflaters, Dither, FillMode, IndexSet,
+    OutputFormat, Reductions, Region, Regions, RowFilter, StripChunks, Target, TargetColors, diff,
+    reduce, side_by_side,
+};
+use image::{DynamicImage, ImageError, ImageFormat};
+use memmap2::{Mmap, MmapOptions};
+use rayon::prelude::*;
+
+use crate::batch::{Glob, Job, collect_jobs, output_path};
+use crate::report::{Report, ReportFormat};
 
 fn main() -> Result<(), Error> {
     let args = Args::parse();
-    if args.output.is_none() && !args.force {
+    let mut bits = args.bits;
+    if let Some(alpha) = args.alpha_bits {
+        if bits.alpha.is_some() {
+            eprintln!(
+                "\
+                You cannot supply '--alpha-bits' if '--bits' already contains a value for the \
+                alpha channel.\n\
+                \n\
+                For more information, try '--help'."
+            );
+            exit(1);
+        }
+        bits.alpha = Some(alpha);
+    }
+    bits.clean_alpha = args.clean_alpha;
+    bits.alpha_weighted = args.alpha_weighted;
+    let mut config = args.config(bits);
+    if let Some(path) = &args.roi_mask {
+        let mask = image::open(path).map_err(|err| Error::RoiMask(err, path.clone()))?;
+        config.regions.mask = Some(mask.into_luma8());
+    }
+
+    if args.out_dir.is_none() && !args.in_place {
+        let (input, output) = match args.paths.as_slice() {
+            [input] => (input.clone(), None),
+            [input, output] => (input.clone(), Some(output.clone())),
+            _ => {
+                eprintln!(
+                    "\
+                    You have to supply '--out-dir' or '--in-place' to process more than one \
+                    file.\n\
+                    \n\
+                    For more information, try '--help'."
+                );
+                exit(1);
+            }
+        };
+        // an explicit output, including "-", is used as it is
+        let output = match output {
+            Some(output) => Some(output),
+            None if input == Path::new("-") => None,
+            None => output_path(&input, None, args.format),
+        };
+        if output.is_none() && input != Path::new("-") && !args.force && !args.dry_run {
+            eprintln!(
+                "\
+                You have to supply an output path, or supply the '--force' option.\n\
+                \n\
+                For more information, try '--help'."
+            );
+            exit(1);
+        }
+        if input.is_dir() {
+            eprintln!(
+                "\
+                You have to supply '--out-dir' or '--in-place' to process a directory.\n\
+                \n\
+                For more information, try '--help'."
+            );
+            exit(1);
+        }
+        return process(&args, &config, input, output);
+    }
+
+    if args.paths.iter().any(|path| path == Path::new("-")) {
         eprintln!(
             "\
-            You have to supply an output path, or supply the '--force' option.\n\
+            You cannot read from stdin together with '--out-dir' or '--in-place'.\n\
             \n\
             For more information, try '--help'."
         );
         exit(1);
     }
 
+    let mut errors = Vec::new();
+    let jobs = collect_jobs(
+        &args.paths,
+        args.out_dir.as_deref(),
+        &args.include,
+        &args.exclude,
+        args.format,
+        &mut errors,
+    );
+    let total = jobs.len() + errors.len();
+    let failures: Vec<_> = jobs
+        .into_par_iter()
+        .filter_map(|Job { input, output }| {
+            if !args.dry_run
+                && let Some(dir) = output.as_deref().and_then(Path::parent)
+                && let Err(err) = create_dir_all(dir)
+            {
+                return Some((input, Error::CreateDir(err, dir.to_owned())));
+            }
+            process(&args, &config, input.clone(), output)
+                .err()
+                .map(|err| (input, err))
+        })
+        .collect();
+
+    if errors.is_empty() && failures.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "Could not process {} of {total} files.",
+        errors.len() + failures.len(),
+    );
+    for err in errors {
+        eprintln!("\n{err:?}");
+    }
+    for (input, err) in failures {
+        eprintln!("\n{input:?}: {err:?}");
+    }
+    exit(1);
+}
+
+fn process(
+    args: &Args,
+    config: &Config,
+    input_path: PathBuf,
+    output_path: Option<PathBuf>,
+) -> Result<(), Error> {
     // open input and output files
 
-    let file = match OpenOptions::new()
-        .read(true)
-        .write(args.output.is_none())
-        .open(&args.input)
-    {
-        Ok(input) => input,
-        Err(err) => return Err(Error::OpenRead(err, args.input)),
-    };
-    let input = match unsafe { MmapOptions::new().map(&file) } {
-        Ok(input) => input,
-        Err(err) => return Err(Error::Map(err, args.input)),
+    let (input, is_stdin) = if input_path == Path::new("-") {
+        let mut input = Vec::new();
+        if let Err(err) = stdin().lock().read_to_end(&mut input) {
+            return Err(Error::Stdin(err));
+        }
+        (Input::Buffered(input), true)
+    } else {
+        let file = match File::open(&input_path) {
+            Ok(input) => input,
+            Err(err) => return Err(Error::OpenRead(err, input_path)),
+        };
+        match unsafe { MmapOptions::new().map(&file) } {
+            Ok(input) => (Input::Mapped(input), false),
+            Err(err) => return Err(Error::Map(err, input_path)),
+        }
     };
 
-    let mut output = if let Some(path) = args.output {
-        drop(file);
-        match OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .create_new(!args.force)
-            .open(&path)
-        {
-            Ok(file) => Output::NewFile(file, path),
-            Err(err) => return Err(Error::OpenWrite(err, path)),
+    let output = match output_path {
+        _ if args.dry_run => Output::DryRun,
+        Some(path) if path == Path::new("-") => Output::Stdout(stdout().lock()),
+        Some(path) => {
+            match OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .create_new(!args.force)
+                .open(&path)
+            {
+                Ok(file) => Output::NewFile(file, path),
+                Err(err) => return Err(Error::OpenWrite(err, path)),
+            }
         }
-    } else {
-        Output::Inplace(file)
+        None if is_stdin => Output::Stdout(stdout().lock()),
+        None => Output::Inplace,
     };
 
-    // read input
+    // reduce bits
 
-    let input = match PngDecoder::new(Cursor::new(input)) {
-        Ok(input) => input,
-        Err(err) => return Err(Error::Header(err, args.input)),
+    let reduced = match reduce(input.as_ref(), config) {
+        Ok(reduced) => reduced,
+        Err(err) => {
+            // do not leave an empty file behind, e.g. for unsupported input formats
+            if let Output::NewFile(_, path) = &output
+                && !args.force
+            {
+                let _ = remove_file(path);
+            }
+            return Err(Error::Reduce(err, input_path));
+        }
     };
+    if args.target().is_some() {
+        report_bits(&input_path, reduced.bits, reduced.target_colors);
+    }
+    let (optimized, bits, reductions) = (reduced.output, reduced.bits, reduced.reductions);
+
+    // write output
 
-    let target_colors = match TargetColors::try_from(input.color_type()) {
-        Ok(target_colors) => target_colors,
-        Err(color_type) => return Err(Error::ColorType(color_type, args.input)),
+    // other formats are converted, so the input cannot be kept
+    let keep_input = optimized.len() >= input.as_ref().len()
+        && !args.always_write
+        && args.format.matches(input.as_ref());
+    let (written, reductions) = if keep_input {
+        (input.as_ref(), Reductions::default())
+    } else {
+        (optimized.as_slice(), reductions)
     };
+    if let Some(format) = args.report_format() {
+        let bits = (!keep_input).then_some(bits);
+        let report = match Report::new(&input_path, input.as_ref(), written, bits, reductions) {
+            Ok(report) => report.format(format),
+            Err(err) => return Err(Error::Compare(err, input_path)),
+        };
+        match output {
+            Output::Stdout(_) => eprintln!("{report}"),
+            _ => println!("{report}"),
+        }
+    }
+    if let Some(path) = &args.diff {
+        let diff = match diff(input.as_ref(), written) {
+            Ok(diff) => DynamicImage::from(diff),
+            Err(err) => return Err(Error::Compare(err, input_path)),
+        };
+        write_preview(&diff, path)?;
+    }
+    if let Some(path) = &args.side_by_side {
+        match side_by_side(input.as_ref(), written) {
+            Ok(composite) => write_preview(&composite, path)?,
+            Err(err) => return Err(Error::Compare(err, input_path)),
+        }
+    }
 
-    let (width, height) = input.dimensions();
-    let mut image = vec![0; input.total_bytes().try_into().unwrap()];
-    if let Err(err) = input.read_image(&mut image) {
-        return Err(Error::Read(err, args.input));
+    let optimized = if !keep_input {
+        optimized
+    } else if let Output::Inplace = output {
+        return Ok(());
+    } else {
+        input.as_ref().to_vec()
+    };
+    let input_size = input.as_ref().len();
+    drop(input);
+
+    match output {
+        Output::Inplace => replace_file(&input_path, &optimized, args.preserve_mtime),
+        Output::NewFile(mut file, path) => {
+            if let Err(err) = file.write_all(optimized.as_slice()) {
+                return Err(Error::Write(err, path));
+            }
+            if let Err(err) = file.set_len(optimized.len().try_into().unwrap()) {
+                return Err(Error::Truncate(err, path));
+            }
+            Ok(())
+        }
+        Output::Stdout(mut stdout) => {
+            match stdout
+                .write_all(optimized.as_slice())
+                .and_then(|()| stdout.flush())
+            {
+                Ok(()) => Ok(()),
+                Err(err) => Err(Error::Stdout(err)),
+            }
+        }
+        Output::DryRun => {
+            if args.report_format().is_none() {
+                let path = input_path.display();
+                println!("{path}: {input_size} -> {} bytes", optimized.len());
+            }
+            Ok(())
+        }
     }
+}
 
-    // re-encode image
+fn write_preview(image: &DynamicImage, path: &Path) -> Result<(), Error> {
+    match image.save_with_format(path, ImageFormat::Png) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(Error::Preview(err, path.to_owned())),
+    }
+}
 
-    args.bits.run(&mut image, target_colors);
+/// Print the number of color bits a quality target chose.
+fn report_bits(input_path: &Path, bits: ChannelBits, target_colors: TargetColors) {
+    let kept = bits.gray().get().min(target_colors.depth());
+    eprintln!("{}: kept {kept} bits", input_path.display());
+}
 
-    let mut encoded = Vec::new();
-    PngEncoder::new_with_quality(&mut encoded, CompressionType::Fast, FilterType::NoFilter)
-        .write_image(&{ image }, width, height, target_colors.into())
-        .map_err(Error::Encode)?;
+/// Write `data` into a temporary file next to `path`, then rename it over `path`,
+/// so `path` is never left half-written.
+///
+/// If `path` is a symlink, the file it points to is replaced and the link is kept.
+fn replace_file(path: &Path, data: &[u8], preserve_mtime: bool) -> Result<(), Error> {
+    let path = match canonicalize(path) {
+        Ok(path) => path,
+        Err(err) => return Err(Error::Resolve(err, path.to_owned())),
+    };
+    let metadata = match metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(err) => return Err(Error::Metadata(err, path.clone())),
+    };
 
-    let options = Options {
-        strip: StripChunks::All,
-        deflate: Deflaters::Zopfli {
-            iterations: args.iterations,
-        },
-        fast_evaluation: false,
-        timeout: Some(args.timeout.into()),
-        ..Options::from_preset(6)
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut attempt = 0_u32;
+    let (file, temp) = loop {
+        let temp = dir.join(format!(".{name}.{}.{attempt}.tmp", process::id()));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => break (file, temp),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(err) => return Err(Error::OpenWrite(err, temp)),
+        }
     };
-    let optimized = optimize_from_memory(&{ encoded }, &options).map_err(Error::Optimize)?;
 
-    // write output
+    let result = fill_temp_file(file, &temp, data, &metadata, preserve_mtime);
+    let result = result.and_then(|()| match rename(&temp, &path) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(Error::Rename(err, path.clone())),
+    });
+    if result.is_err() {
+        let _ = remove_file(&temp);
+        return result;
+    }
 
-    let file = match &mut output {
-        Output::Inplace(file) => match file.seek(SeekFrom::Start(0)) {
-            Ok(_) => file,
-            Err(err) => return Err(Error::Seek(err, args.input)),
-        },
-        Output::NewFile(file, _) => file,
-    };
-    if let Err(err) = file.write_all(optimized.as_slice()) {
-        let path = match output {
-            Output::Inplace(_) => args.input,
-            Output::NewFile(_, path) => path,
-        };
-        return Err(Error::Write(err, path));
+    // the rename itself only survives a crash once the directory was flushed, too
+    #[cfg(unix)]
+    if let Err(err) = File::open(dir).and_then(|dir| dir.sync_all()) {
+        return Err(Error::Sync(err, dir.to_owned()));
+    }
+    Ok(())
+}
+
+fn fill_temp_file(
+    mut file: File,
+    temp: &Path,
+    data: &[u8],
+    metadata: &Metadata,
+    preserve_mtime: bool,
+) -> Result<(), Error> {
+    if let Err(err) = file.write_all(data) {
+        return Err(Error::Write(err, temp.to_owned()));
     }
-    if let Output::Inplace(file) = output
-        && let Err(err) = file.set_len(optimized.len().try_into().unwrap())
+    if let Err(err) = file.set_permissions(metadata.permissions()) {
+        return Err(Error::CopyMetadata(err, temp.to_owned()));
+    }
+    if preserve_mtime
+        && let Err(err) = metadata
+            .modified()
+            .and_then(|mtime| file.set_times(FileTimes::new().set_modified(mtime)))
     {
-        return Err(Error::Truncate(err, args.input));
+        return Err(Error::CopyMetadata(err, temp.to_owned()));
+    }
+    if let Err(err) = file.sync_all() {
+        return Err(Error::Sync(err, temp.to_owned()));
     }
-
     Ok(())
 }
 
+enum Input {
+    Mapped(Mmap),
+    Buffered(Vec<u8>),
+}
+
+impl AsRef<[u8]> for Input {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Input::Mapped(input) => input,
+            Input::Buffered(input) => input,
+        }
+    }
+}
+
 enum Output {
-    Inplace(File),
+    Inplace,
     NewFile(File, PathBuf),
+    Stdout(StdoutLock<'static>),
+    DryRun,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum SignificantBits {
-    Bits1,
-    Bits2,
-    Bits3,
-    Bits4,
-    Bits5,
-    Bits6,
-    Bits7,
-    Bits8,
-}
+/// Which ancillary chunks to keep in the output.
+#[derive(Debug, Clone)]
+struct Strip(StripChunks);
 
-impl SignificantBits {
-    fn run(self, bytes: &mut [u8], target_colors: TargetColors) {
-        use SignificantBits::*;
-        use TargetColors::*;
-
-        let func = match (target_colors, self) {
-            (_, Bits8) => return,
-            (L8 | Rgb8, Bits1) => no_alpha::<0b1000_0000>,
-            (L8 | Rgb8, Bits2) => no_alpha::<0b1100_0000>,
-            (L8 | Rgb8, Bits3) => no_alpha::<0b1110_0000>,
-            (L8 | Rgb8, Bits4) => no_alpha::<0b1111_0000>,
-            (L8 | Rgb8, Bits5) => no_alpha::<0b1111_1000>,
-            (L8 | Rgb8, Bits6) => no_alpha::<0b1111_1100>,
-            (L8 | Rgb8, Bits7) => no_alpha::<0b1111_1110>,
-            (La8, Bits1) => la8::<0b1000_0000>,
-            (La8, Bits2) => la8::<0b1100_0000>,
-            (La8, Bits3) => la8::<0b1110_0000>,
-            (La8, Bits4) => la8::<0b1111_0000>,
-            (La8, Bits5) => la8::<0b1111_1000>,
-            (La8, Bits6) => la8::<0b1111_1100>,
-            (La8, Bits7) => la8::<0b1111_1110>,
-            (Rgba8, Bits1) => rgba::<0b1000_0000>,
-            (Rgba8, Bits2) => rgba::<0b1100_0000>,
-            (Rgba8, Bits3) => rgba::<0b1110_0000>,
-            (Rgba8, Bits4) => rgba::<0b1111_0000>,
-            (Rgba8, Bits5) => rgba::<0b1111_1000>,
-            (Rgba8, Bits6) => rgba::<0b1111_1100>,
-            (Rgba8, Bits7) => rgba::<0b1111_1110>,
-        };
-        func(bytes);
+impl FromStr for Strip {
+    type Err = &'static str;
 
-        fn no_alpha<const MASK: u8>(bytes: &mut [u8]) {
-            for byte in bytes {
-                mask_bits::<MASK>(byte);
-            }
-        }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERR: &str =
+            "expected \"all\", \"safe\", \"none\" or \"keep=\" with comma-separated chunk names";
 
-        fn rgba<const MASK: u8>(bytes: &mut [u8]) {
-            let (chunks, _) = bytes.as_chunks_mut::<4>();
-            for chunk in chunks {
-                mask_bits::<MASK>(&mut chunk[0]);
-                mask_bits::<MASK>(&mut chunk[1]);
-                mask_bits::<MASK>(&mut chunk[2]);
+        match s.trim_ascii() {
+            "all" => Ok(Self(StripChunks::All)),
+            "safe" => Ok(Self(StripChunks::Safe)),
+            "none" => Ok(Self(StripChunks::None)),
+            s => {
+                let names = s.strip_prefix("keep=").ok_or(ERR)?;
+                let names = names
+                    .split(',')
+                    .map(|name| name.trim_ascii().as_bytes().try_into().map_err(|_| ERR))
+                    .collect::<Result<IndexSet<[u8; 4]>, _>>()?;
+                Ok(Self(StripChunks::Keep(names)))
             }
         }
+    }
+}
 
-        fn la8<const MASK: u8>(bytes: &mut [u8]) {
-            let (chunks, _) = bytes.as_chunks_mut::<2>();
-            for chunk in chunks {
-                mask_bits::<MASK>(&mut chunk[0]);
-            }
-        }
+/// Optimization level of oxipng.
+#[derive(Debug, Clone, Copy)]
+struct Preset(u8);
 
-        #[inline(always)]
-        fn mask_bits<const MASK: u8>(byte: &mut u8) {
-            *byte = (*byte & MASK) | const { (!MASK) >> 1 };
+impl FromStr for Preset {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_ascii() {
+            "max" => Ok(Self(6)),
+            s => match s.parse() {
+                Ok(level @ 0..=6) => Ok(Self(level)),
+                _ => Err("expected a number between 0 and 6, or \"max\""),
+            },
         }
     }
 }
 
+/// Compression algorithm of the output, the number of Zopfli iterations is a separate option.
 #[derive(Debug, Clone, Copy)]
-enum TargetColors {
-    L8,
-    La8,
-    Rgb8,
-    Rgba8,
+enum Deflater {
+    Zopfli,
+    Libdeflater(u8),
 }
 
-impl From<TargetColors> for ExtendedColorType {
-    fn from(value: TargetColors) -> ExtendedColorType {
-        match value {
-            TargetColors::L8 => ExtendedColorType::L8,
-            TargetColors::La8 => ExtendedColorType::La8,
-            TargetColors::Rgb8 => ExtendedColorType::Rgb8,
-            TargetColors::Rgba8 => ExtendedColorType::Rgba8,
-        }
-    }
-}
+impl FromStr for Deflater {
+    type Err = &'static str;
 
-impl TryFrom<ColorType> for TargetColors {
-    type Error = ColorType;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERR: &str = "expected \"zopfli\", \"libdeflater\" or \"libdeflater=\" with a level between 0 and 12";
 
-    fn try_from(value: ColorType) -> Result<Self, Self::Error> {
-        match value {
-            ColorType::L8 => Ok(Self::L8),
-            ColorType::La8 => Ok(Self::La8),
-            ColorType::Rgb8 => Ok(Self::Rgb8),
-            ColorType::Rgba8 => Ok(Self::Rgba8),
-            value => Err(value),
+        match s.trim_ascii() {
+            "zopfli" => Ok(Self::Zopfli),
+            "libdeflater" => Ok(Self::Libdeflater(12)),
+            s => {
+                let level = s.strip_prefix("libdeflater=").ok_or(ERR)?;
+                match level.trim_ascii().parse() {
+                    Ok(level @ 0..=12) => Ok(Self::Libdeflater(level)),
+                    _ => Err(ERR),
+                }
+            }
         }
     }
 }
 
-impl FromStr for SignificantBits {
+/// Filter strategies to try.
+#[derive(Debug, Clone)]
+struct Filters(IndexSet<RowFilter>);
+
+impl FromStr for Filters {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.trim_ascii() {
-            "1" => Ok(Self::Bits1),
-            "2" => Ok(Self::Bits2),
-            "3" => Ok(Self::Bits3),
-            "4" => Ok(Self::Bits4),
-            "5" => Ok(Self::Bits5),
-            "6" => Ok(Self::Bits6),
-            "7" => Ok(Self::Bits7),
-            "8" => Ok(Self::Bits8),
-            _ => Err("expected value between 1 and 8"),
-        }
+        const ERR: &str = "expected comma-separated numbers between 0 and 9";
+
+        let filters = s
+            .split(',')
+            .map(|filter| {
+                let filter = filter.trim_ascii().parse::<u8>().map_err(|_| ERR)?;
+                RowFilter::try_from(filter).map_err(|()| ERR)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self(filters))
     }
 }
 
@@ -254,46 +489,220 @@ git_testament::git_testament_macros!(git);
 #[derive(Debug, Parser)]
 #[clap(version = git_testament!())]
 struct Args {
-    /// read from
-    input: PathBuf,
-    /// write to (default: overwrite input)
-    output: Option<PathBuf>,
+    /// read from, and write to (default: overwrite input): "INPUT [OUTPUT]", where "-" is
+    /// stdin or stdout, or any number of input files and directories with '--out-dir' or
+    /// '--in-place'
+    #[clap(required = true, value_name = "PATH")]
+    paths: Vec<PathBuf>,
+    /// write into this directory, mirroring the structure of input directories
+    #[clap(long, conflicts_with = "in_place")]
+    out_dir: Option<PathBuf>,
+    /// overwrite every input file
+    #[clap(long, action)]
+    in_place: bool,
+    /// only process files in input directories that match this pattern (default: files with the
+    /// extension of a readable format, e.g. "*.png")
+    #[clap(long, value_name = "GLOB")]
+    include: Vec<Glob>,
+    /// skip files and directories in input directories that match this pattern
+    #[clap(long, value_name = "GLOB")]
+    exclude: Vec<Glob>,
+    /// write the result even if it is not smaller than the input
+    #[clap(long, action)]
+    always_write: bool,
+    /// keep the modification time of files that are overwritten in place
+    #[clap(long, action)]
+    preserve_mtime: bool,
     /// overwrite existing output file if exists
     #[clap(long, short, action)]
     force: bool,
-    /// number of significant bits to keep
+    /// number of significant bits to keep, for all color channels or comma-separated per
+    /// channel, e.g. "5,6,5" or "4,4,4,4"
     #[arg(long, short, default_value = "6")]
-    bits: SignificantBits,
-    /// compression iterations
+    bits: ChannelBits,
+    /// reduce to an indexed image with at most this many colors (2 to 256)
+    #[arg(long, value_parser = clap::value_parser!(u16).range(2..=256))]
+    palette: Option<u16>,
+    /// instead of '--bits', keep the fewest color bits (1 to 8) whose largest sample error, in 8
+    /// bit units, does not exceed this value
+    #[arg(long, conflicts_with_all = ["bits", "min_psnr", "min_ssim"])]
+    max_error: Option<f64>,
+    /// instead of '--bits', keep the fewest color bits (1 to 8) that reach this PSNR in dB
+    #[arg(long, conflicts_with_all = ["bits", "min_ssim"])]
+    min_psnr: Option<f64>,
+    /// instead of '--bits', keep the fewest color bits (1 to 8) that reach this SSIM (0 to 1)
+    #[arg(long, conflicts_with = "bits")]
+    min_ssim: Option<f64>,
+    /// value of the discarded bits: "midpoint", "zero", "replicate" or "round" (to the nearest
+    /// replicated value); grayscale images with 1, 2 or 4 bits per sample always use "replicate"
+    #[arg(long, default_value = "midpoint")]
+    fill: FillMode,
+    /// dither the color channels: "none", "floyd-steinberg", "atkinson" or "bayer"
+    #[arg(long, default_value = "none")]
+    dither: Dither,
+    /// number of significant bits to keep in the alpha channel, or "binary" (default: all)
+    #[arg(long)]
+    alpha_bits: Option<AlphaBits>,
+    /// replace the color of fully transparent pixels with "zero" (black), or "previous" (the
+    /// color of the preceding pixel in the same row)
+    #[arg(
+        long,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "zero"
+    )]
+    clean_alpha: Option<CleanAlpha>,
+    /// keep fewer color bits in translucent pixels: one bit fewer for each halving of alpha, but
+    /// at least 1, e.g. with '--bits 5', pixels with an alpha of 64 to 127 keep 4 bits, and those
+    /// with an alpha of 32 to 63 keep 3 bits
+    #[clap(long, action)]
+    alpha_weighted: bool,
+    /// grayscale image of the same size, whose white pixels keep all color bits, black pixels
+    /// keep '--bits', and gray pixels keep a number of bits in between
+    #[arg(long, value_name = "MASK.png")]
+    roi_mask: Option<PathBuf>,
+    /// rectangle "X,Y,WIDTH,HEIGHT=BITS" that keeps its own number of color bits, can be
+    /// repeated; later rectangles take precedence over earlier ones and over '--roi-mask'
+    #[arg(long, value_name = "X,Y,WIDTH,HEIGHT=BITS")]
+    roi: Vec<Region>,
+    /// write 16-bit images with 8 bits per channel if at most 8 bits are kept
+    #[clap(long, action)]
+    reduce_depth: bool,
+    /// format of the output: "png", "qoi" or "webp-lossless" (both 8-bit RGB or RGBA, without
+    /// sBIT or metadata, and need the "qoi" or "webp" cargo feature)
+    #[arg(long, default_value = "png")]
+    format: OutputFormat,
+    /// which metadata chunks to remove: "all", "safe" (keep those that affect rendering),
+    /// "none", or "keep=" with a comma-separated list of chunk names to keep
+    #[clap(long, default_value = "all")]
+    strip: Strip,
+    /// do not record the number of significant bits in an sBIT chunk
+    #[clap(long, action)]
+    no_sbit: bool,
+    /// do not write the output file, only print its projected size
+    #[clap(long, action)]
+    dry_run: bool,
+    /// print sizes and quality metrics of each file
+    #[clap(long, action)]
+    report: bool,
+    /// format of '--report': "human" or "json" (one object per line); implies '--report'
+    #[arg(long)]
+    report_format: Option<ReportFormat>,
+    /// write a heatmap of the difference between input and output, amplified so that the
+    /// largest difference is white
+    #[clap(long, value_name = "OUTPUT.png", conflicts_with_all = ["out_dir", "in_place"])]
+    diff: Option<PathBuf>,
+    /// write the input and the output next to each other
+    #[clap(long, value_name = "OUTPUT.png", conflicts_with_all = ["out_dir", "in_place"])]
+    side_by_side: Option<PathBuf>,
+    /// optimization level of oxipng: 0 (fastest) to 6, or "max"
+    #[arg(long, short = 'o', default_value = "6")]
+    preset: Preset,
+    /// compression algorithm: "zopfli", "libdeflater" (level 12) or "libdeflater=" with a level
+    /// between 0 and 12
+    #[arg(long, default_value = "zopfli")]
+    deflater: Deflater,
+    /// compression iterations of zopfli
     #[clap(long, short, default_value = "15")]
     iterations: NonZeroU8,
+    /// comma-separated filter strategies to try (default: those of '--preset'): 0 to 4 for none,
+    /// sub, up, average and paeth, 5 to 9 for min-sum, entropy, bigrams, big-ent and brute
+    #[arg(long)]
+    filters: Option<Filters>,
+    /// interlace the output with Adam7, which makes it larger, but lets it render progressively
+    #[clap(long, action)]
+    interlace: bool,
+    /// choose the filter strategy by a quick compression instead of the final one
+    #[clap(long, action)]
+    fast_evaluation: bool,
     /// maximum amount of time to spend on optimizations
     #[clap(long, short, default_value = "30s")]
     timeout: humantime::Duration,
 }
 
+impl Args {
+    fn target(&self) -> Option<Target> {
+        let max_error = self.max_error.map(Target::MaxError);
+        let min_psnr = self.min_psnr.map(Target::MinPsnr);
+        let min_ssim = self.min_ssim.map(Target::MinSsim);
+        max_error.or(min_psnr).or(min_ssim)
+    }
+
+    fn config(&self, bits: ChannelBits) -> Config {
+        Config {
+            bits,
+            regions: Regions {
+                mask: None,
+                rects: self.roi.clone(),
+            },
+            target: self.target(),
+            fill: self.fill,
+            dither: self.dither,
+            palette: self.palette,
+            reduce_depth: self.reduce_depth,
+            strip: self.strip.0.clone(),
+            sbit: !self.no_sbit,
+            preset: self.preset.0,
+            deflate: match self.deflater {
+                Deflater::Zopfli => Deflaters::Zopfli {
+                    iterations: self.iterations,
+                },
+                Deflater::Libdeflater(compression) => Deflaters::Libdeflater { compression },
+            },
+            filters: self.filters.as_ref().map(|filters| filters.0.clone()),
+            interlace: self.interlace,
+            fast_evaluation: self.fast_evaluation,
+            timeout: Some(self.timeout.into()),
+            format: self.format,
+        }
+    }
+
+    fn report_format(&self) -> Option<ReportFormat> {
+        match (self.report, self.report_format) {
+            (_, Some(format)) => Some(format),
+            (true, None) => Some(ReportFormat::Human),
+            (false, None) => None,
+        }
+    }
+}
+
 #[derive(pretty_error_debug::Debug, thiserror::Error, displaydoc::Display)]
 enum Error {
+    /// Could not read directory {1:?}.
+    ReadDir(#[source] std::io::Error, PathBuf),
+    /// Could not create directory {1:?}.
+    CreateDir(#[source] std::io::Error, PathBuf),
+    /// Could not read from stdin.
+    Stdin(#[source] std::io::Error),
+    /// Could not write to stdout.
+    Stdout(#[source] std::io::Error),
     /// Could not open {1:?} for reading.
     OpenRead(#[source] std::io::Error, PathBuf),
     /// Could not map {1:?} for reading.
     Map(#[source] std::io::Error, PathBuf),
-    /// Could not decode image header of {1:?}.
-    Header(#[source] ImageError, PathBuf),
-    /// Color type {0:?} of {1:?} is not supported. Only L8, La8, Rgb8 and Rgba8 are.
-    ColorType(ColorType, PathBuf),
-    /// Could not read image data of {1:?}.
-    Read(#[source] ImageError, PathBuf),
-    /// Could not encode image.
-    Encode(#[source] ImageError),
-    /// Could not optimize image.
-    Optimize(#[source] PngError),
+    /// Could not reduce the bits of {1:?}.
+    Reduce(#[source] fewerpngbits::Error, PathBuf),
+    /// Could not compare {1:?} to its result.
+    Compare(#[source] fewerpngbits::Error, PathBuf),
+    /// Could not read region mask {1:?}.
+    RoiMask(#[source] ImageError, PathBuf),
+    /// Could not write preview image {1:?}.
+    Preview(#[source] ImageError, PathBuf),
     /// Could not open {1:?} for writing.
     OpenWrite(#[source] std::io::Error, PathBuf),
     /// Could not write image {1:?}.
     Write(#[source] std::io::Error, PathBuf),
-    /// Could not seek to the start of {1:?}.
-    Seek(#[source] std::io::Error, PathBuf),
     /// Could not empty output file {1:?}.
     Truncate(#[source] std::io::Error, PathBuf),
+    /// Could not resolve the path {1:?}.
+    Resolve(#[source] std::io::Error, PathBuf),
+    /// Could not read metadata of {1:?}.
+    Metadata(#[source] std::io::Error, PathBuf),
+    /// Could not copy permissions or modification time to {1:?}.
+    CopyMetadata(#[source] std::io::Error, PathBuf),
+    /// Could not flush {1:?} to disk.
+    Sync(#[source] std::io::Error, PathBuf),
+    /// Could not replace {1:?}.
+    Rename(#[source] std::io::Error, PathBuf),
 }
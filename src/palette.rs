@@ -0,0 +1,196 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::TargetColors;
+
+/// Number of k-means iterations that refine the median cut palette.
+const REFINEMENTS: usize = 4;
+
+/// Convert every pixel to 8-bit RGBA, keeping only the high byte of 16-bit samples.
+pub(crate) fn rgba_pixels(bytes: &[u8], target_colors: TargetColors) -> Vec<[u8; 4]> {
+    let bytes = match target_colors.narrowed() {
+        Some(_) => &crate::narrow_samples(bytes),
+        None => bytes,
+    };
+    match target_colors.channels() {
+        1 => bytes.iter().map(|&l| [l, l, l, 0xff]).collect(),
+        2 => {
+            let (chunks, _) = bytes.as_chunks::<2>();
+            chunks.iter().map(|&[l, a]| [l, l, l, a]).collect()
+        }
+        3 => {
+            let (chunks, _) = bytes.as_chunks::<3>();
+            chunks.iter().map(|&[r, g, b]| [r, g, b, 0xff]).collect()
+        }
+        _ => bytes.as_chunks::<4>().0.to_vec(),
+    }
+}
+
+/// Convert every pixel to 8-bit RGBA like [`rgba_pixels`], but give all fully transparent pixels
+/// the same color, so that they share a single palette entry.
+pub(crate) fn palette_pixels(bytes: &[u8], target_colors: TargetColors) -> Vec<[u8; 4]> {
+    let mut pixels = rgba_pixels(bytes, target_colors);
+    for pixel in &mut pixels {
+        if pixel[3] == 0 {
+            *pixel = [0; 4];
+        }
+    }
+    pixels
+}
+
+/// Find at most `max_colors` colors that represent `pixels` well.
+///
+/// If there are not more distinct colors than that, they are used as they are.
+/// Otherwise the colors are split by median cut, and the result is refined with k-means.
+/// The colors are processed in sorted order, so the palette is the same for every run.
+pub(crate) fn build_palette(pixels: &[[u8; 4]], max_colors: usize) -> Vec<[u8; 4]> {
+    let mut histogram = BTreeMap::<[u8; 4], u64>::new();
+    for &pixel in pixels {
+        *histogram.entry(pixel).or_default() += 1;
+    }
+    let mut colors: Vec<_> = histogram.into_iter().collect();
+    if colors.len() <= max_colors {
+        return colors.into_iter().map(|(color, _)| color).collect();
+    }
+
+    let mut palette: Vec<_> = median_cut(&mut colors, max_colors)
+        .into_iter()
+        .map(|range| mean(&colors[range]))
+        .collect();
+    for _ in 0..REFINEMENTS {
+        let mut sums = vec![([0_u64; 4], 0_u64); palette.len()];
+        for &(color, count) in &colors {
+            let (sum, total) = &mut sums[nearest(&palette, color)];
+            for (sum, value) in sum.iter_mut().zip(color) {
+                *sum += u64::from(value) * count;
+            }
+            *total += count;
+        }
+        for (entry, (sum, total)) in palette.iter_mut().zip(sums) {
+            if total > 0 {
+                *entry = sum.map(|sum| ((sum + total / 2) / total) as u8);
+            }
+        }
+    }
+    palette
+}
+
+/// Encode `pixels` as an indexed PNG, mapping each pixel to the nearest `palette` entry.
+///
+/// The image is compressed quickly, because it is optimized afterwards anyway.
+pub(crate) fn encode_indexed(
+    pixels: &[[u8; 4]],
+    palette: &[[u8; 4]],
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, png::EncodingError> {
+    let indices = palette_indices(pixels, palette);
+    let mut encoded = Vec::new();
+    let mut encoder = png::Encoder::new(&mut encoded, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Fastest);
+    encoder.set_filter(png::Filter::NoFilter);
+    encoder.set_palette(
+        palette
+            .iter()
+            .flat_map(|&[r, g, b, _]| [r, g, b])
+            .collect::<Vec<_>>(),
+    );
+    if palette.iter().any(|&[.., a]| a != 0xff) {
+        encoder.set_trns(palette.iter().map(|&[.., a]| a).collect::<Vec<_>>());
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&indices)?;
+    writer.finish()?;
+    Ok(encoded)
+}
+
+/// Map each pixel to the index of the nearest `palette` entry.
+pub(crate) fn palette_indices(pixels: &[[u8; 4]], palette: &[[u8; 4]]) -> Vec<u8> {
+    let mut lookup = HashMap::new();
+    pixels
+        .iter()
+        .map(|&pixel| {
+            *lookup
+                .entry(pixel)
+                .or_insert_with(|| nearest(palette, pixel) as u8)
+        })
+        .collect()
+}
+
+/// Split `colors` into at most `max_colors` ranges of similar colors.
+fn median_cut(colors: &mut [([u8; 4], u64)], max_colors: usize) -> Vec<std::ops::Range<usize>> {
+    let mut boxes = Vec::with_capacity(max_colors);
+    boxes.push(0..colors.len());
+    while boxes.len() < max_colors {
+        // split the box with the widest channel, weighted by the number of pixels in it
+        let Some((index, channel, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.len() > 1)
+            .map(|(index, range)| {
+                let colors = &colors[range.clone()];
+                let pixels: u64 = colors.iter().map(|&(_, count)| count).sum();
+                let (channel, extent) = (0..4)
+                    .map(|channel| {
+                        let values = colors.iter().map(|(color, _)| color[channel]);
+                        let extent = values.clone().max().unwrap() - values.min().unwrap();
+                        (channel, extent)
+                    })
+                    .max_by_key(|&(_, extent)| extent)
+                    .unwrap();
+                (index, channel, u64::from(extent) * pixels)
+            })
+            .max_by_key(|&(.., score)| score)
+            .filter(|&(.., score)| score > 0)
+        else {
+            break;
+        };
+
+        let range = boxes.swap_remove(index);
+        let colors = &mut colors[range.clone()];
+        colors.sort_by_key(|&(color, _)| (color[channel], color));
+        let half = colors.iter().map(|&(_, count)| count).sum::<u64>() / 2;
+        let mut seen = 0;
+        let split = colors
+            .iter()
+            .position(|&(_, count)| {
+                seen += count;
+                seen > half
+            })
+            .unwrap_or(0)
+            .clamp(1, colors.len() - 1);
+        boxes.push(range.start..range.start + split);
+        boxes.push(range.start + split..range.end);
+    }
+    boxes
+}
+
+fn mean(colors: &[([u8; 4], u64)]) -> [u8; 4] {
+    let total: u64 = colors.iter().map(|&(_, count)| count).sum();
+    [0, 1, 2, 3].map(|channel| {
+        let sum: u64 = colors
+            .iter()
+            .map(|&(color, count)| u64::from(color[channel]) * count)
+            .sum();
+        ((sum + total / 2) / total) as u8
+    })
+}
+
+fn nearest(palette: &[[u8; 4]], color: [u8; 4]) -> usize {
+    let distance = |entry: &[u8; 4]| -> u32 {
+        entry
+            .iter()
+            .zip(color)
+            .map(|(&a, b)| u32::from(a.abs_diff(b)).pow(2))
+            .sum()
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| distance(entry))
+        .map_or(0, |(index, _)| index)
+}
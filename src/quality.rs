@@ -0,0 +1,285 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+use image::{DynamicImage, GenericImageView, RgbImage, imageops};
+
+use crate::dither::Dither;
+use crate::fill::FillMode;
+use crate::{
+    ChannelBits, CleanAlpha, Error, SignificantBits, TargetColors, clean_alpha, convert, load,
+};
+
+/// Side length of the square windows SSIM is computed over.
+const WINDOW: usize = 8;
+const C1: f64 = 0.01 * 0.01;
+const C2: f64 = 0.03 * 0.03;
+
+/// How close the reduced image has to stay to the original.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// Largest difference of any color sample, in 8 bit units.
+    MaxError(f64),
+    /// Smallest peak signal-to-noise ratio in dB.
+    MinPsnr(f64),
+    /// Smallest mean structural similarity.
+    MinSsim(f64),
+}
+
+impl Target {
+    /// Find the fewest color bits between 1 and 8 whose result meets the target.
+    ///
+    /// Dithering and masking are applied to a copy of `image` exactly as they will be later on.
+    /// If no bit count meets the target, all bits are kept.
+    pub(crate) fn choose(
+        self,
+        image: &[u8],
+        width: u32,
+        target_colors: TargetColors,
+        bits: ChannelBits,
+        fill: FillMode,
+        dither: Dither,
+    ) -> ChannelBits {
+        // the color of transparent pixels is invisible, so the original is compared cleaned, too
+        let cleaned = bits.clean_alpha.map(|clean| {
+            let mut cleaned = image.to_vec();
+            clean_alpha(&mut cleaned, width, target_colors, clean);
+            cleaned
+        });
+        let original = Image::new(cleaned.as_deref().unwrap_or(image), width, target_colors);
+        let mut candidate = Vec::with_capacity(image.len());
+        for &color in &SignificantBits::ALL[..8] {
+            let bits = ChannelBits {
+                color: [color; 3],
+                ..bits
+            };
+            candidate.clear();
+            candidate.extend_from_slice(image);
+            dither.run(&mut candidate, width, target_colors, bits, fill);
+            bits.run(&mut candidate, width, target_colors, fill);
+
+            let reduced = Image::new(&candidate, width, target_colors);
+            let channels = target_colors.color_channels();
+            let met = match self {
+                Self::MaxError(max) => max_error(&original, &reduced, channels) <= max,
+                Self::MinPsnr(min) => psnr(mse(&original, &reduced, channels)) >= min,
+                Self::MinSsim(min) => ssim(&original, &reduced, channels) >= min,
+            };
+            if met {
+                return bits;
+            }
+        }
+        ChannelBits {
+            color: [SignificantBits::Bits16; 3],
+            ..bits
+        }
+    }
+}
+
+/// Differences between an image and its reduced version, measured on every channel, but not on
+/// the invisible color of fully transparent pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    /// Width of the original image.
+    pub width: u32,
+    /// Height of the original image.
+    pub height: u32,
+    /// Layout of the original samples, which the reduced image is converted to.
+    pub target_colors: TargetColors,
+    /// Mean squared error in 8 bit units.
+    pub mse: f64,
+    /// Peak signal-to-noise ratio in dB; infinite for identical images.
+    pub psnr: f64,
+    /// Mean structural similarity.
+    pub ssim: f64,
+}
+
+/// Decode two images and measure how much `reduced` differs from `original`.
+pub fn compare(original: &[u8], reduced: &[u8]) -> Result<Metrics, Error> {
+    let (original, reduced, target_colors) = decode_pair(original, reduced)?;
+    let (width, height) = original.dimensions();
+
+    // the color of fully transparent pixels is invisible, so it is not compared
+    let [original, reduced] = [original, reduced].map(|image| {
+        let mut bytes = image.into_bytes();
+        clean_alpha(&mut bytes, width, target_colors, CleanAlpha::Zero);
+        bytes
+    });
+    let original = Image::new(&original, width, target_colors);
+    let reduced = Image::new(&reduced, width, target_colors);
+    let channels = target_colors.channels();
+    let mse = mse(&original, &reduced, channels);
+    Ok(Metrics {
+        width,
+        height,
+        target_colors,
+        mse: mse * f64::from(u8::MAX) * f64::from(u8::MAX),
+        psnr: psnr(mse),
+        ssim: ssim(&original, &reduced, channels),
+    })
+}
+
+/// Decode two images and render the largest difference of each pixel as a heatmap.
+///
+/// The differences are amplified, so that the largest one in the image is white,
+/// while identical pixels stay black.
+pub fn diff(original: &[u8], reduced: &[u8]) -> Result<RgbImage, Error> {
+    let (original, reduced, target_colors) = decode_pair(original, reduced)?;
+    let (width, height) = original.dimensions();
+
+    let original = Image::new(original.as_bytes(), width, target_colors);
+    let reduced = Image::new(reduced.as_bytes(), width, target_colors);
+    let differences = (0..original.height())
+        .flat_map(|y| (0..original.width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            (0..target_colors.channels())
+                .map(|c| (original.get(x, y, c) - reduced.get(x, y, c)).abs())
+                .fold(0.0, f64::max)
+        })
+        .collect::<Vec<_>>();
+    let max = differences.iter().copied().fold(0.0, f64::max);
+
+    let pixels = differences
+        .into_iter()
+        .flat_map(|difference| match max {
+            0.0 => [0; 3],
+            max => heat(difference / max),
+        })
+        .collect();
+    Ok(RgbImage::from_raw(width, height, pixels).unwrap())
+}
+
+/// Decode two images and place them next to each other, `original` on the left.
+pub fn side_by_side(original: &[u8], reduced: &[u8]) -> Result<DynamicImage, Error> {
+    let (original, reduced, _) = decode_pair(original, reduced)?;
+    let (width, height) = original.dimensions();
+
+    let mut composite = DynamicImage::new(2 * width, height, original.color());
+    imageops::replace(&mut composite, &original, 0, 0);
+    imageops::replace(&mut composite, &reduced, width.into(), 0);
+    Ok(composite)
+}
+
+/// Map `0.0..=1.0` to black, red, yellow and white.
+fn heat(value: f64) -> [u8; 3] {
+    [0.0, 1.0, 2.0].map(|offset| ((3.0 * value - offset).clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Decode two images, converting `reduced` into the sample layout of `original`.
+fn decode_pair(
+    original: &[u8],
+    reduced: &[u8],
+) -> Result<(DynamicImage, DynamicImage, TargetColors), Error> {
+    let (original, target_colors) = load(original)?;
+    let (reduced, _) = load(reduced)?;
+    if reduced.dimensions() != original.dimensions() {
+        return Err(Error::Dimensions);
+    }
+    Ok((original, convert(reduced, target_colors), target_colors))
+}
+
+/// Read-only view of a decoded image with samples scaled to `0.0..=1.0`.
+#[derive(Debug, Clone, Copy)]
+struct Image<'a> {
+    bytes: &'a [u8],
+    depth: u8,
+    channels: usize,
+    width: usize,
+}
+
+impl<'a> Image<'a> {
+    fn new(bytes: &'a [u8], width: u32, target_colors: TargetColors) -> Self {
+        Self {
+            bytes,
+            depth: target_colors.depth(),
+            channels: target_colors.channels(),
+            width: width.try_into().unwrap(),
+        }
+    }
+
+    fn height(&self) -> usize {
+        let bytes_per_row = self.width * self.channels * usize::from(self.depth / 8);
+        self.bytes.len().checked_div(bytes_per_row).unwrap_or(0)
+    }
+
+    fn get(&self, x: usize, y: usize, channel: usize) -> f64 {
+        let index = (y * self.width + x) * self.channels + channel;
+        match self.depth {
+            8 => f64::from(self.bytes[index]) / f64::from(u8::MAX),
+            _ => {
+                let value = u16::from_ne_bytes([self.bytes[2 * index], self.bytes[2 * index + 1]]);
+                f64::from(value) / f64::from(u16::MAX)
+            }
+        }
+    }
+
+    /// Iterate over the first `channels` samples of every pixel of both images.
+    fn pairs<'b>(
+        &'b self,
+        other: &'b Image<'_>,
+        channels: usize,
+    ) -> impl Iterator<Item = (f64, f64)> + 'b {
+        (0..self.height()).flat_map(move |y| {
+            (0..self.width).flat_map(move |x| {
+                (0..channels).map(move |c| (self.get(x, y, c), other.get(x, y, c)))
+            })
+        })
+    }
+}
+
+/// Largest difference of the first `channels` samples of each pixel, in 8 bit units.
+fn max_error(original: &Image<'_>, reduced: &Image<'_>, channels: usize) -> f64 {
+    original
+        .pairs(reduced, channels)
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f64::max)
+        * f64::from(u8::MAX)
+}
+
+/// Mean squared error of the first `channels` samples of each pixel.
+fn mse(original: &Image<'_>, reduced: &Image<'_>, channels: usize) -> f64 {
+    let (sum, count) = original
+        .pairs(reduced, channels)
+        .fold((0.0, 0usize), |(sum, count), (a, b)| {
+            (sum + (a - b) * (a - b), count + 1)
+        });
+    if count == 0 { 0.0 } else { sum / count as f64 }
+}
+
+/// Peak signal-to-noise ratio in dB for a mean squared error; infinite for identical images.
+fn psnr(mse: f64) -> f64 {
+    -10.0 * mse.log10()
+}
+
+/// Mean structural similarity over non-overlapping windows of the first `channels` channels.
+fn ssim(original: &Image<'_>, reduced: &Image<'_>, channels: usize) -> f64 {
+    let height = original.height();
+    let mut sum = 0.0;
+    let mut count = 0usize;
+    for top in (0..height).step_by(WINDOW) {
+        for left in (0..original.width).step_by(WINDOW) {
+            let ys = top..(top + WINDOW).min(height);
+            let xs = left..(left + WINDOW).min(original.width);
+            let n = (ys.len() * xs.len()) as f64;
+            for channel in 0..channels {
+                let mut stats = [0.0; 5];
+                for y in ys.clone() {
+                    for x in xs.clone() {
+                        let a = original.get(x, y, channel);
+                        let b = reduced.get(x, y, channel);
+                        stats[0] += a;
+                        stats[1] += b;
+                        stats[2] += a * a;
+                        stats[3] += b * b;
+                        stats[4] += a * b;
+                    }
+                }
+                let [a, b, aa, bb, ab] = stats.map(|stat| stat / n);
+                let (var_a, var_b, cov) = (aa - a * a, bb - b * b, ab - a * b);
+                sum += ((2.0 * a * b + C1) * (2.0 * cov + C2))
+                    / ((a * a + b * b + C1) * (var_a + var_b + C2));
+                count += 1;
+            }
+        }
+    }
+    if count == 0 { 1.0 } else { sum / count as f64 }
+}
@@ -0,0 +1,127 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::TargetColors;
+
+/// Color type reductions that were applied to the masked samples before encoding.
+///
+/// Masking often makes channels redundant, e.g. if the discarded bits were the only
+/// difference between the color channels, or the only reason why alpha was not opaque.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Reductions {
+    /// The alpha channel was dropped, because every pixel is opaque.
+    pub opaque: bool,
+    /// The color channels were merged, because every pixel is gray.
+    pub gray: bool,
+    /// The image was written with a palette, because it has at most 256 colors.
+    pub indexed: bool,
+}
+
+impl Reductions {
+    /// Names of the applied reductions.
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.opaque, "opaque"),
+            (self.gray, "gray"),
+            (self.indexed, "indexed"),
+        ]
+        .into_iter()
+        .filter_map(|(applied, name)| applied.then_some(name))
+    }
+}
+
+impl fmt::Display for Reductions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self.names().collect::<Vec<_>>();
+        match names.is_empty() {
+            true => f.write_str("none"),
+            false => f.write_str(&names.join(",")),
+        }
+    }
+}
+
+/// Drop an alpha channel that is opaque everywhere, and merge color channels that are equal
+/// everywhere, unless `allow_gray` is unset.
+pub(crate) fn reduce_channels(
+    bytes: Vec<u8>,
+    target_colors: TargetColors,
+    allow_gray: bool,
+    reductions: &mut Reductions,
+) -> (Vec<u8>, TargetColors) {
+    let sample = usize::from(target_colors.depth() / 8);
+    let channels = target_colors.channels();
+    let color_channels = target_colors.color_channels();
+    let pixels = bytes.chunks_exact(channels * sample);
+
+    let has_alpha = channels > color_channels;
+    let opaque = has_alpha
+        && pixels
+            .clone()
+            .all(|pixel| pixel[color_channels * sample..].iter().all(|&b| b == 0xff));
+    let gray = allow_gray
+        && color_channels == 3
+        && pixels.clone().all(|pixel| {
+            let (rgb, _) = pixel.split_at(3 * sample);
+            let (r, gb) = rgb.split_at(sample);
+            gb.chunks_exact(sample).all(|sample| sample == r)
+        });
+    if !opaque && !gray {
+        return (bytes, target_colors);
+    }
+
+    let mut kept = match gray {
+        true => vec![0],
+        false => (0..color_channels).collect(),
+    };
+    if has_alpha && !opaque {
+        kept.push(color_channels);
+    }
+    let reduced = pixels
+        .flat_map(|pixel| {
+            kept.iter()
+                .flat_map(move |&channel| &pixel[channel * sample..(channel + 1) * sample])
+        })
+        .copied()
+        .collect();
+    let target_colors = match (kept.len(), sample) {
+        (1, 1) => TargetColors::L8,
+        (2, 1) => TargetColors::La8,
+        (3, 1) => TargetColors::Rgb8,
+        (_, 1) => TargetColors::Rgba8,
+        (1, _) => TargetColors::L16,
+        (2, _) => TargetColors::La16,
+        (3, _) => TargetColors::Rgb16,
+        (_, _) => TargetColors::Rgba16,
+    };
+    reductions.opaque = opaque;
+    reductions.gray = gray;
+    (reduced, target_colors)
+}
+
+/// Convert an 8-bit RGB or RGBA image to RGBA pixels, keeping the color of transparent pixels.
+pub(crate) fn color_pixels(bytes: &[u8], target_colors: TargetColors) -> Option<Vec<[u8; 4]>> {
+    match target_colors {
+        TargetColors::Rgb8 => {
+            let (chunks, _) = bytes.as_chunks::<3>();
+            Some(chunks.iter().map(|&[r, g, b]| [r, g, b, 0xff]).collect())
+        }
+        TargetColors::Rgba8 => Some(bytes.as_chunks::<4>().0.to_vec()),
+        _ => None,
+    }
+}
+
+/// Find the colors of `pixels`, if there are not more than 256.
+///
+/// The palette is sorted to make the output deterministic.
+pub(crate) fn exact_palette(pixels: &[[u8; 4]]) -> Option<Vec<[u8; 4]>> {
+    let mut palette = BTreeSet::new();
+    for &pixel in pixels {
+        if palette.insert(pixel) && palette.len() > 256 {
+            return None;
+        }
+    }
+    Some(palette.into_iter().collect())
+}
@@ -0,0 +1,166 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use image::{GrayImage, Luma};
+
+use crate::dither::Dither;
+use crate::fill::FillMode;
+use crate::{ChannelBits, SignificantBits, TargetColors};
+
+/// Parts of the image that keep a different number of color bits than [`Config::bits`].
+///
+/// [`Config::bits`]: crate::Config::bits
+#[derive(Debug, Clone, Default)]
+pub struct Regions {
+    /// Grayscale image with the dimensions of the input: black pixels keep the configured bits,
+    /// white pixels keep all bits, and gray values lie in between.
+    pub mask: Option<GrayImage>,
+    /// Rectangles with their own color bits, later ones take precedence over earlier ones and
+    /// over the `mask`.
+    pub rects: Vec<Region>,
+}
+
+/// A rectangle that keeps its own number of color bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bits: SignificantBits,
+}
+
+impl Regions {
+    pub fn is_empty(&self) -> bool {
+        self.mask.is_none() && self.rects.is_empty()
+    }
+
+    /// Whether the `mask` does not cover an image of this size exactly.
+    pub(crate) fn mismatches(&self, width: u32, height: u32) -> bool {
+        self.mask
+            .as_ref()
+            .is_some_and(|mask| mask.dimensions() != (width, height))
+    }
+
+    /// Dither and mask every pixel with the bits of its region, and return the most bits that
+    /// were kept in each channel.
+    ///
+    /// Each distinct number of bits is applied to a copy of the whole image, so that dithering
+    /// and filling work exactly as without regions, then the pixels of its regions are copied back.
+    pub(crate) fn run(
+        &self,
+        bytes: &mut [u8],
+        width: u32,
+        target_colors: TargetColors,
+        bits: ChannelBits,
+        fill: FillMode,
+        dither: Dither,
+    ) -> ChannelBits {
+        let pixel_size = target_colors.channels() * usize::from(target_colors.depth() / 8);
+        let levels = self.levels(bytes.len() / pixel_size, width, bits, target_colors.depth());
+
+        let original = bytes.to_vec();
+        let mut kept = ChannelBits {
+            color: [SignificantBits::Bits1; 3],
+            ..bits
+        };
+        let mut candidate = Vec::with_capacity(original.len());
+        for level in levels.iter().copied().collect::<BTreeSet<_>>() {
+            let level_bits = match level {
+                Some(level) => ChannelBits {
+                    color: [level; 3],
+                    ..bits
+                },
+                None => bits,
+            };
+            for (kept, level) in kept.color.iter_mut().zip(level_bits.color) {
+                *kept = (*kept).max(level);
+            }
+
+            candidate.clear();
+            candidate.extend_from_slice(&original);
+            dither.run(&mut candidate, width, target_colors, level_bits, fill);
+            level_bits.run(&mut candidate, width, target_colors, fill);
+            let pixels = bytes.chunks_exact_mut(pixel_size);
+            let masked = candidate.chunks_exact(pixel_size);
+            for ((pixel, masked), _) in pixels
+                .zip(masked)
+                .zip(&levels)
+                .filter(|(_, pixel_level)| **pixel_level == level)
+            {
+                pixel.copy_from_slice(masked);
+            }
+        }
+        kept
+    }
+
+    /// The color bits of every pixel, or `None` where `bits` apply.
+    fn levels(
+        &self,
+        count: usize,
+        width: u32,
+        bits: ChannelBits,
+        depth: u8,
+    ) -> Vec<Option<SignificantBits>> {
+        let width = usize::try_from(width).unwrap();
+        let mut levels = vec![None; count];
+        if let Some(mask) = &self.mask {
+            let base = bits.gray().get().min(depth);
+            for (index, level) in levels.iter_mut().enumerate() {
+                let (x, y) = ((index % width) as u32, (index / width) as u32);
+                if let Some(&Luma([value])) = mask.get_pixel_checked(x, y)
+                    && value > 0
+                {
+                    let extra = (u32::from(value) * u32::from(depth - base) + 127) / 255;
+                    *level = Some(SignificantBits::ALL[usize::from(base) + extra as usize - 1]);
+                }
+            }
+        }
+        for rect in &self.rects {
+            let xs = rect.x as usize..(rect.x as usize + rect.width as usize).min(width);
+            for y in rect.y as usize..rect.y as usize + rect.height as usize {
+                let Some(row) = levels.get_mut(y * width..(y + 1) * width) else {
+                    break;
+                };
+                if let Some(row) = row.get_mut(xs.clone()) {
+                    row.fill(Some(rect.bits));
+                }
+            }
+        }
+        levels
+    }
+}
+
+impl FromStr for Region {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERR: &str = "expected \"X,Y,WIDTH,HEIGHT=BITS\" with BITS between 1 and 16";
+
+        let (rect, bits) = s.trim_ascii().split_once('=').ok_or(ERR)?;
+        let mut values = [0; 4];
+        let mut count = 0;
+        for value in rect.split(',') {
+            let Some(slot) = values.get_mut(count) else {
+                return Err(ERR);
+            };
+            *slot = value.trim_ascii().parse().map_err(|_| ERR)?;
+            count += 1;
+        }
+        if count != 4 {
+            return Err(ERR);
+        }
+
+        let [x, y, width, height] = values;
+        Ok(Self {
+            x,
+            y,
+            width,
+            height,
+            bits: bits.parse().map_err(|_| ERR)?,
+        })
+    }
+}
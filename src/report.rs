@@ -0,0 +1,231 @@
+// SPDX-FileCopyrightText: 2025 René Kijewski <crates.io@k6i.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0 OR ISC
+
+use std::fmt::{self, Write};
+use std::io::Cursor;
+use std::path::Path;
+use std::str::FromStr;
+
+use fewerpngbits::{ChannelBits, Error, OutputFormat, Reductions, TargetColors, compare};
+use png::ColorType;
+
+/// How to print the statistics of each file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReportFormat {
+    Human,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_ascii() {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err("expected \"human\" or \"json\""),
+        }
+    }
+}
+
+/// Sizes and quality metrics of one processed file.
+#[derive(Debug, Clone)]
+pub(crate) struct Report {
+    path: String,
+    input_size: usize,
+    output_size: usize,
+    width: u32,
+    height: u32,
+    color_type: &'static str,
+    depth: u8,
+    /// The bits that were kept, or `None` if the input was kept because the result was not
+    /// smaller.
+    bits: Option<Vec<u8>>,
+    reductions: Reductions,
+    /// Mean squared error in 8 bit units.
+    mse: f64,
+    psnr: f64,
+    ssim: f64,
+}
+
+impl Report {
+    /// Compare the decoded `output` against the decoded `input`.
+    ///
+    /// `bits` is `None` if `output` is the unchanged input.
+    pub(crate) fn new(
+        path: &Path,
+        input: &[u8],
+        output: &[u8],
+        bits: Option<ChannelBits>,
+        reductions: Reductions,
+    ) -> Result<Self, Error> {
+        let metrics = compare(input, output)?;
+
+        let depth = metrics.target_colors.depth();
+        let bits = bits.map(|bits| {
+            let [r, g, b] = bits.color.map(|bits| bits.get().min(depth));
+            let gray = bits.gray().get().min(depth);
+            let alpha = bits.alpha.map_or(depth, |alpha| alpha.get().min(depth));
+            match metrics.target_colors {
+                TargetColors::L8 | TargetColors::L16 => vec![gray],
+                TargetColors::La8 | TargetColors::La16 => vec![gray, alpha],
+                TargetColors::Rgb8 | TargetColors::Rgb16 => vec![r, g, b],
+                TargetColors::Rgba8 | TargetColors::Rgba16 => vec![r, g, b, alpha],
+            }
+        });
+
+        let (color_type, depth) = color_type(output);
+
+        Ok(Self {
+            path: path.display().to_string(),
+            input_size: input.len(),
+            output_size: output.len(),
+            width: metrics.width,
+            height: metrics.height,
+            color_type,
+            depth,
+            bits,
+            reductions,
+            mse: metrics.mse,
+            psnr: metrics.psnr,
+            ssim: metrics.ssim,
+        })
+    }
+
+    fn savings(&self) -> f64 {
+        match self.input_size {
+            0 => 0.0,
+            size => 100.0 * (size as f64 - self.output_size as f64) / size as f64,
+        }
+    }
+
+    fn bits(&self) -> Option<String> {
+        let bits = self.bits.as_ref()?.iter().map(u8::to_string);
+        Some(bits.collect::<Vec<_>>().join(","))
+    }
+
+    fn reductions(&self) -> String {
+        let names = self.reductions.names().map(|name| format!("\"{name}\""));
+        names.collect::<Vec<_>>().join(",")
+    }
+
+    /// Render the report on a single line.
+    pub(crate) fn format(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Human => self.to_string(),
+            ReportFormat::Json => {
+                let mut json = String::new();
+                json.push('{');
+                json.push_str("\"path\":");
+                json_string(&mut json, &self.path);
+                write!(
+                    json,
+                    ",\"input_size\":{},\"output_size\":{},\"savings_percent\":{:.2},\
+                    \"width\":{},\"height\":{},\"color_type\":\"{}\",\"bit_depth\":{},\
+                    \"kept_original\":{},\"bits\":",
+                    self.input_size,
+                    self.output_size,
+                    self.savings(),
+                    self.width,
+                    self.height,
+                    self.color_type,
+                    self.depth,
+                    self.bits.is_none(),
+                )
+                .unwrap();
+                match self.bits() {
+                    Some(bits) => write!(json, "[{bits}]"),
+                    None => write!(json, "null"),
+                }
+                .unwrap();
+                write!(
+                    json,
+                    ",\"reductions\":[{}],\"mse\":{:.6},\"psnr\":",
+                    self.reductions(),
+                    self.mse,
+                )
+                .unwrap();
+                match self.psnr.is_finite() {
+                    true => write!(json, "{:.4}", self.psnr),
+                    false => write!(json, "null"),
+                }
+                .unwrap();
+                write!(json, ",\"ssim\":{:.6}}}", self.ssim).unwrap();
+                json
+            }
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {} bytes ({:.1}% saved), {}x{} {} {}-bit, ",
+            self.path,
+            self.input_size,
+            self.output_size,
+            self.savings(),
+            self.width,
+            self.height,
+            self.color_type,
+            self.depth,
+        )?;
+        match self.bits() {
+            Some(bits) => write!(f, "bits {bits}, reduced {}", self.reductions)?,
+            None => write!(f, "kept original")?,
+        }
+        write!(f, ", MSE {:.3}, PSNR ", self.mse)?;
+        match self.psnr.is_finite() {
+            true => write!(f, "{:.2} dB", self.psnr)?,
+            false => write!(f, "inf")?,
+        }
+        write!(f, ", SSIM {:.4}", self.ssim)
+    }
+}
+
+/// Color type and bit depth of the output, read from its header.
+fn color_type(output: &[u8]) -> (&'static str, u8) {
+    // QOI stores the number of channels after the magic bytes and dimensions
+    if OutputFormat::Qoi.matches(output) {
+        return match output.get(12) {
+            Some(3) => ("rgb", 8),
+            _ => ("rgba", 8),
+        };
+    }
+    // lossless WebP flags the use of alpha after the signature byte and the dimensions
+    if OutputFormat::WebpLossless.matches(output) {
+        return match output.get(21..25) {
+            Some(&[.., flags]) if flags & 0x10 != 0 => ("rgba", 8),
+            Some(_) => ("rgb", 8),
+            None => ("unknown", 0),
+        };
+    }
+    match png::Decoder::new(Cursor::new(output)).read_info() {
+        Ok(reader) => {
+            let info = reader.info();
+            let color_type = match info.color_type {
+                ColorType::Grayscale => "gray",
+                ColorType::Rgb => "rgb",
+                ColorType::Indexed => "indexed",
+                ColorType::GrayscaleAlpha => "gray-alpha",
+                ColorType::Rgba => "rgba",
+            };
+            (color_type, info.bit_depth as u8)
+        }
+        Err(_) => ("unknown", 0),
+    }
+}
+
+fn json_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            c if c.is_control() => write!(output, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}